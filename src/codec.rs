@@ -0,0 +1,65 @@
+use fast_collections::Cursor;
+
+use crate::framing::{LengthDelimited, LineDelimited};
+
+mod listener;
+pub use listener::*;
+
+pub trait Codec {
+    type Message;
+
+    /// Decodes the next message from `read_buf`, advancing `pos` past the consumed bytes.
+    /// Returns `None` until a complete message is buffered.
+    fn decode<const N: usize>(
+        &self,
+        read_buf: &mut Cursor<u8, N>,
+    ) -> Result<Option<Self::Message>, ()>;
+
+    fn encode<const N: usize>(
+        &self,
+        message: &Self::Message,
+        write_buf: &mut Cursor<u8, N>,
+    ) -> Result<(), ()>;
+}
+
+impl Codec for LengthDelimited {
+    type Message = Vec<u8>;
+
+    fn decode<const N: usize>(
+        &self,
+        read_buf: &mut Cursor<u8, N>,
+    ) -> Result<Option<Self::Message>, ()> {
+        Ok(self
+            .next_frame(read_buf)?
+            .map(|frame| read_buf.filled()[frame].to_vec()))
+    }
+
+    fn encode<const N: usize>(
+        &self,
+        message: &Self::Message,
+        write_buf: &mut Cursor<u8, N>,
+    ) -> Result<(), ()> {
+        LengthDelimited::encode(self, write_buf, message)
+    }
+}
+
+impl Codec for LineDelimited {
+    type Message = String;
+
+    fn decode<const N: usize>(
+        &self,
+        read_buf: &mut Cursor<u8, N>,
+    ) -> Result<Option<Self::Message>, ()> {
+        Ok(self
+            .next_line(read_buf)?
+            .map(|line| String::from_utf8_lossy(&read_buf.filled()[line]).into_owned()))
+    }
+
+    fn encode<const N: usize>(
+        &self,
+        message: &Self::Message,
+        write_buf: &mut Cursor<u8, N>,
+    ) -> Result<(), ()> {
+        LineDelimited::encode(self, write_buf, message)
+    }
+}
@@ -0,0 +1,208 @@
+use std::{hash::Hash, net::SocketAddr};
+
+use derive_more::{Deref, DerefMut};
+use fast_collections::Cursor;
+use qcell::{LCell, LCellOwner};
+
+use crate::{
+    framing::compact,
+    pool::BufferStrategy,
+    socket::{Encode, ServerSocketListener, Socket, Sockets},
+};
+
+use super::Codec;
+
+#[derive(Deref, DerefMut)]
+#[repr(transparent)]
+pub struct MessageListener<T>(pub T);
+
+pub trait MessageHandler<'id>: Sized {
+    const MAX_CONNECTIONS: usize;
+    const READ_BUFFFER_LEN: usize;
+    const WRITE_BUFFER_LEN: usize;
+    const BUFFER_STRATEGY: BufferStrategy = BufferStrategy::Dedicated;
+    const WRITE_HIGH_WATER_MARK: usize = 0;
+    type Connection: Default;
+    type RoomId: Eq + Hash + Clone = ();
+    type Codec: Codec;
+    const CODEC: Self::Codec;
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, MessageListener<Self>>,
+    ) where
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    fn on_open(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) where
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    fn on_message(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+        message: <Self::Codec as Codec>::Message,
+    ) where
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    /// Called when `send` hit `WRITE_HIGH_WATER_MARK`. Closes the connection by default.
+    fn on_write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) where
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:,
+    {
+        connection.register_close_event(owner);
+    }
+
+    fn on_close(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) where
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <MessageListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+}
+
+struct Encoded<'a, C: Codec>(&'a C, &'a C::Message);
+
+impl<C: Codec> Encode for Encoded<'_, C> {
+    fn encode<const N: usize>(&self, write_buf: &mut Cursor<u8, N>) -> Result<(), ()> {
+        self.0.encode(self.1, write_buf)
+    }
+}
+
+impl<'id, T: MessageHandler<'id>> MessageListener<T> {
+    fn inner<'a>(server: &'a LCell<'id, Self>) -> &'a LCell<'id, T> {
+        unsafe { &*(server as *const LCell<'id, Self> as *const LCell<'id, T>) }
+    }
+}
+
+impl<'id, T: MessageHandler<'id>> ServerSocketListener<'id> for MessageListener<T> {
+    const MAX_CONNECTIONS: usize = T::MAX_CONNECTIONS;
+    const READ_BUFFFER_LEN: usize = T::READ_BUFFFER_LEN;
+    const WRITE_BUFFER_LEN: usize = T::WRITE_BUFFER_LEN;
+    const BUFFER_STRATEGY: BufferStrategy = T::BUFFER_STRATEGY;
+    const WRITE_HIGH_WATER_MARK: usize = T::WRITE_HIGH_WATER_MARK;
+    type Connection = T::Connection;
+    type RoomId = T::RoomId;
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::tick(Self::inner(server), owner, sockets)
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: SocketAddr,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::on_open(owner, Self::inner(server), connection)
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        loop {
+            match T::CODEC.decode(connection.read_buf.rw(owner)) {
+                Ok(Some(message)) => T::on_message(owner, Self::inner(server), connection, message),
+                Ok(None) => break compact(connection.read_buf.rw(owner)),
+                Err(()) => break connection.register_close_event(owner),
+            }
+        }
+    }
+
+    fn read_buffer_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        connection.register_close_event(owner)
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+    }
+
+    fn write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::on_write_queue_full(owner, Self::inner(server), connection)
+    }
+
+    fn close(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::on_close(owner, Self::inner(server), connection)
+    }
+}
+
+impl<'id, T: MessageHandler<'id>> Socket<'id, '_, MessageListener<T>>
+where
+    [(); <MessageListener<T> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+    [(); <MessageListener<T> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+    [(); <MessageListener<T> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:,
+{
+    pub fn send(
+        &mut self,
+        owner: &mut LCellOwner<'id>,
+        message: &<T::Codec as Codec>::Message,
+    ) -> Result<(), ()> {
+        self.write_with(owner, &Encoded(&T::CODEC, message))
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    rc::Rc,
+    time::Instant,
+};
+
+use fast_collections::{Cursor, Vec};
+use mio::net::UdpSocket;
+use qcell::{LCell, LCellOwner};
+
+use crate::{selector::Poll, socket::SocketState};
+
+pub trait DatagramListener<'id>: Sized {
+    const READ_BUFFFER_LEN: usize;
+    const WRITE_BUFFER_LEN: usize;
+    const MAX_QUEUED_DATAGRAMS: usize;
+
+    fn tick(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        socket: &mut DatagramSocket<'id, Self>,
+    ) where
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_QUEUED_DATAGRAMS]:;
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        socket: &mut DatagramSocket<'id, Self>,
+        addr: SocketAddr,
+        datagram: &[u8],
+    ) where
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_QUEUED_DATAGRAMS]:;
+}
+
+pub struct DatagramSocket<'id, T: DatagramListener<'id>>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_QUEUED_DATAGRAMS]:,
+{
+    pub write_buf: LCell<'id, Cursor<u8, { T::WRITE_BUFFER_LEN }>>,
+    pub(crate) queue: Vec<(SocketAddr, usize), { T::MAX_QUEUED_DATAGRAMS }>,
+    pub(crate) state: SocketState,
+}
+
+impl<'id, T: DatagramListener<'id>> DatagramSocket<'id, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_QUEUED_DATAGRAMS]:,
+{
+    pub fn new() -> Self {
+        Self {
+            write_buf: Default::default(),
+            queue: Default::default(),
+            state: SocketState::default(),
+        }
+    }
+
+    pub fn send_to(
+        &mut self,
+        owner: &mut LCellOwner<'id>,
+        addr: SocketAddr,
+        datagram: &[u8],
+    ) -> Result<(), ()> {
+        let write_buf = self.write_buf.rw(owner);
+        if self.queue.len() == self.queue.capacity()
+            || write_buf.filled_len() + datagram.len() > T::WRITE_BUFFER_LEN
+        {
+            return Err(());
+        }
+        write_buf.write_all(datagram).map_err(|_| ())?;
+        unsafe { self.queue.push_unchecked((addr, datagram.len())) };
+        self.state = SocketState::WriteRequest;
+        Ok(())
+    }
+
+    pub fn queued_len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+impl<'id, T: DatagramListener<'id>> Default for DatagramSocket<'id, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_QUEUED_DATAGRAMS]:,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) struct DatagramSelector<'id, T: DatagramListener<'id>>
+where
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_QUEUED_DATAGRAMS]:,
+{
+    pub server: LCell<'id, T>,
+    pub socket: DatagramSocket<'id, T>,
+    pub udp_socket: UdpSocket,
+    read_buf: [u8; T::READ_BUFFFER_LEN],
+}
+
+impl<'id, T: DatagramListener<'id>> DatagramSelector<'id, T>
+where
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_QUEUED_DATAGRAMS]:,
+{
+    pub fn new(server: T, owner: &mut LCellOwner<'id>, udp_socket: UdpSocket) -> Self {
+        Self {
+            server: owner.cell(server),
+            socket: DatagramSocket::new(),
+            udp_socket,
+            read_buf: [0; T::READ_BUFFFER_LEN],
+        }
+    }
+
+    pub fn tick(&mut self, owner: &mut LCellOwner<'id>) {
+        T::tick(owner, &self.server, &mut self.socket)
+    }
+
+    pub fn read(&mut self, owner: &mut LCellOwner<'id>) {
+        while let Ok((read_len, addr)) = self.udp_socket.recv_from(&mut self.read_buf) {
+            T::read(
+                owner,
+                &self.server,
+                &mut self.socket,
+                addr,
+                &self.read_buf[..read_len],
+            )
+        }
+    }
+
+    pub fn flush_registry(&mut self, owner: &mut LCellOwner<'id>) {
+        if self.socket.state != SocketState::WriteRequest {
+            return;
+        }
+        let write_buf = self.socket.write_buf.rw(owner);
+        let queue = &mut self.socket.queue;
+        let mut pos = 0;
+        let mut sent = 0;
+        for &(addr, len) in queue.iter() {
+            match self
+                .udp_socket
+                .send_to(&write_buf.filled()[pos..pos + len], addr)
+            {
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                _ => {}
+            }
+            pos += len;
+            sent += 1;
+        }
+        let filled_len = write_buf.filled_len();
+        write_buf.as_array().copy_within(pos..filled_len, 0);
+        unsafe { *write_buf.filled_len_mut() = filled_len - pos };
+        let queued = queue.len();
+        queue.as_array_mut().copy_within(sent..queued, 0);
+        unsafe { *queue.len_mut() = queued - sent };
+        if queue.is_empty() {
+            self.socket.state = SocketState::Idle;
+        }
+    }
+}
+
+pub struct UdpPeer {
+    socket: Rc<UdpSocket>,
+    addr: SocketAddr,
+    inbound: std::vec::Vec<u8>,
+    inbound_limit: usize,
+    last_read: Instant,
+}
+
+impl UdpPeer {
+    /// Datagrams that would grow the unread data past `inbound_limit` bytes are dropped.
+    pub(crate) fn new(socket: Rc<UdpSocket>, addr: SocketAddr, inbound_limit: usize) -> Self {
+        Self {
+            socket,
+            addr,
+            inbound: std::vec::Vec::new(),
+            inbound_limit,
+            last_read: Instant::now(),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn last_read(&self) -> Instant {
+        self.last_read
+    }
+
+    pub(crate) fn receive(&mut self, datagram: &[u8]) {
+        self.last_read = Instant::now();
+        if self.inbound.len() + datagram.len() <= self.inbound_limit {
+            self.inbound.extend_from_slice(datagram);
+        }
+    }
+
+    pub(crate) fn has_inbound(&self) -> bool {
+        !self.inbound.is_empty()
+    }
+}
+
+impl Read for UdpPeer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.inbound.is_empty() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let read_len = buf.len().min(self.inbound.len());
+        buf[..read_len].copy_from_slice(&self.inbound[..read_len]);
+        self.inbound.drain(..read_len);
+        Ok(read_len)
+    }
+}
+
+impl Write for UdpPeer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.socket.send_to(buf, self.addr) {
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct UdpPoll {
+    pub peers: HashMap<SocketAddr, usize>,
+}
+
+impl Poll<UdpPeer> for UdpPoll {
+    fn open(&mut self, stream: &mut UdpPeer, token: usize) -> Result<(), ()> {
+        self.peers.insert(stream.addr, token);
+        Ok(())
+    }
+
+    fn reregister(
+        &mut self,
+        _stream: &mut UdpPeer,
+        _token: usize,
+        _readable: bool,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn close(&mut self, stream: &mut UdpPeer) {
+        self.peers.remove(&stream.addr);
+    }
+}
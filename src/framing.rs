@@ -0,0 +1,239 @@
+use std::{io::Write, ops::Range};
+
+use fast_collections::Cursor;
+
+const MAX_VARINT_LEN: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LengthPrefix {
+    U16Be,
+    U16Le,
+    U32Be,
+    U32Le,
+    Varint,
+}
+
+impl LengthPrefix {
+    pub const fn max_header_len(self) -> usize {
+        match self {
+            Self::U16Be | Self::U16Le => 2,
+            Self::U32Be | Self::U32Le => 4,
+            Self::Varint => MAX_VARINT_LEN,
+        }
+    }
+
+    pub const fn max_len(self) -> usize {
+        match self {
+            Self::U16Be | Self::U16Le => u16::MAX as usize,
+            Self::U32Be | Self::U32Le => u32::MAX as usize,
+            Self::Varint => usize::MAX,
+        }
+    }
+
+    /// Returns the payload length and the header length, or `None` if the header is incomplete.
+    pub fn decode(self, bytes: &[u8]) -> Result<Option<(usize, usize)>, ()> {
+        let header_len = self.max_header_len();
+        Ok(Some(match self {
+            Self::U16Be => match bytes.first_chunk() {
+                Some(header) => (u16::from_be_bytes(*header) as usize, header_len),
+                None => return Ok(None),
+            },
+            Self::U16Le => match bytes.first_chunk() {
+                Some(header) => (u16::from_le_bytes(*header) as usize, header_len),
+                None => return Ok(None),
+            },
+            Self::U32Be => match bytes.first_chunk() {
+                Some(header) => (u32::from_be_bytes(*header) as usize, header_len),
+                None => return Ok(None),
+            },
+            Self::U32Le => match bytes.first_chunk() {
+                Some(header) => (u32::from_le_bytes(*header) as usize, header_len),
+                None => return Ok(None),
+            },
+            Self::Varint => {
+                let mut value = 0usize;
+                for (i, &byte) in bytes.iter().take(MAX_VARINT_LEN).enumerate() {
+                    let bits = (byte & 0x7f) as usize;
+                    let shift = 7 * i as u32;
+                    if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                        return Err(());
+                    }
+                    value |= bits << shift;
+                    if byte & 0x80 == 0 {
+                        return Ok(Some((value, i + 1)));
+                    }
+                }
+                return if bytes.len() >= MAX_VARINT_LEN {
+                    Err(())
+                } else {
+                    Ok(None)
+                };
+            }
+        }))
+    }
+
+    pub fn encode<const N: usize>(
+        self,
+        len: usize,
+        write_buf: &mut Cursor<u8, N>,
+    ) -> Result<(), ()> {
+        if len > self.max_len() {
+            return Err(());
+        }
+        match self {
+            Self::U16Be => write_buf.write_all(&(len as u16).to_be_bytes()),
+            Self::U16Le => write_buf.write_all(&(len as u16).to_le_bytes()),
+            Self::U32Be => write_buf.write_all(&(len as u32).to_be_bytes()),
+            Self::U32Le => write_buf.write_all(&(len as u32).to_le_bytes()),
+            Self::Varint => {
+                let mut header = [0u8; MAX_VARINT_LEN];
+                let mut header_len = 0;
+                let mut value = len;
+                loop {
+                    let byte = (value & 0x7f) as u8;
+                    value >>= 7;
+                    if value == 0 {
+                        header[header_len] = byte;
+                        header_len += 1;
+                        break;
+                    }
+                    header[header_len] = byte | 0x80;
+                    header_len += 1;
+                }
+                write_buf.write_all(&header[..header_len])
+            }
+        }
+        .map_err(|_| ())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LengthDelimited {
+    pub prefix: LengthPrefix,
+    pub max_frame_len: usize,
+}
+
+impl LengthDelimited {
+    pub const fn new(prefix: LengthPrefix, max_frame_len: usize) -> Self {
+        Self {
+            prefix,
+            max_frame_len,
+        }
+    }
+
+    /// Returns the payload range of the next complete frame in `read_buf.filled()` and moves
+    /// `pos` past it. When no complete frame is left the buffer is compacted, which invalidates
+    /// previously returned ranges.
+    pub fn next_frame<const N: usize>(
+        &self,
+        read_buf: &mut Cursor<u8, N>,
+    ) -> Result<Option<Range<usize>>, ()> {
+        let pos = read_buf.pos();
+        let Some((payload_len, header_len)) = self.prefix.decode(&read_buf.filled()[pos..])? else {
+            compact(read_buf);
+            return Ok(None);
+        };
+        if payload_len > self.max_frame_len || header_len.saturating_add(payload_len) > N {
+            return Err(());
+        }
+        let payload_pos = pos + header_len;
+        let payload_end = payload_pos + payload_len;
+        if payload_end > read_buf.filled_len() {
+            compact(read_buf);
+            return Ok(None);
+        }
+        unsafe { *read_buf.pos_mut() = payload_end };
+        Ok(Some(payload_pos..payload_end))
+    }
+
+    pub fn encode<const N: usize>(
+        &self,
+        write_buf: &mut Cursor<u8, N>,
+        payload: &[u8],
+    ) -> Result<(), ()> {
+        if payload.len() > self.max_frame_len {
+            return Err(());
+        }
+        let filled_len = write_buf.filled_len();
+        let result = self
+            .prefix
+            .encode(payload.len(), write_buf)
+            .and_then(|()| write_buf.write_all(payload).map_err(|_| ()));
+        if result.is_err() {
+            unsafe { *write_buf.filled_len_mut() = filled_len };
+        }
+        result
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LineDelimited {
+    pub max_line_len: usize,
+}
+
+impl LineDelimited {
+    pub const fn new(max_line_len: usize) -> Self {
+        Self { max_line_len }
+    }
+
+    /// Returns the range of the next `\n` or `\r\n` terminated line in `read_buf.filled()`,
+    /// without its terminator, and moves `pos` past it. Lines longer than `max_line_len` or
+    /// not valid UTF-8 are rejected.
+    pub fn next_line<const N: usize>(
+        &self,
+        read_buf: &mut Cursor<u8, N>,
+    ) -> Result<Option<Range<usize>>, ()> {
+        let pos = read_buf.pos();
+        let unread = &read_buf.filled()[pos..];
+        let Some(line_len) = unread.iter().position(|&byte| byte == b'\n') else {
+            if unread.len() > self.max_line_len + 1 {
+                return Err(());
+            }
+            compact(read_buf);
+            return Ok(None);
+        };
+        let line = match unread[..line_len] {
+            [ref line @ .., b'\r'] => line,
+            ref line => line,
+        };
+        if line.len() > self.max_line_len || std::str::from_utf8(line).is_err() {
+            return Err(());
+        }
+        let line_end = pos + line.len();
+        unsafe { *read_buf.pos_mut() = pos + line_len + 1 };
+        Ok(Some(pos..line_end))
+    }
+
+    pub fn encode<const N: usize>(
+        &self,
+        write_buf: &mut Cursor<u8, N>,
+        line: &str,
+    ) -> Result<(), ()> {
+        if line.len() > self.max_line_len || line.contains('\n') {
+            return Err(());
+        }
+        let filled_len = write_buf.filled_len();
+        let result = write_buf
+            .write_all(line.as_bytes())
+            .and_then(|()| write_buf.write_all(b"\r\n"))
+            .map_err(|_| ());
+        if result.is_err() {
+            unsafe { *write_buf.filled_len_mut() = filled_len };
+        }
+        result
+    }
+}
+
+/// Moves the unread bytes of `buf` to the front.
+pub fn compact<const N: usize>(buf: &mut Cursor<u8, N>) {
+    let pos = buf.pos();
+    if pos == 0 {
+        return;
+    }
+    let filled_len = buf.filled_len();
+    buf.as_array().copy_within(pos..filled_len, 0);
+    unsafe {
+        *buf.filled_len_mut() = filled_len - pos;
+        *buf.pos_mut() = 0;
+    }
+}
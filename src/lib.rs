@@ -1,10 +1,20 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
+#![feature(associated_type_defaults)]
+#![allow(clippy::result_unit_err)]
 
+pub mod codec;
+pub mod datagram;
+pub mod framing;
+pub mod metrics;
 pub mod mio;
 pub mod mock;
+pub mod pool;
+pub mod room;
 pub mod selector;
 pub mod socket;
 pub mod tick_machine;
+#[cfg(feature = "tls")]
+pub mod tls;
 #[cfg(feature = "websocket")]
 pub mod websocket;
@@ -0,0 +1,152 @@
+use std::{
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use mio::net::{TcpListener, TcpStream};
+
+use crate::selector::Poll;
+
+/// Event loop counters of a listener. Totals count from the start of the listener.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub accepted: u64,
+    pub closed: u64,
+    /// Connections dropped before getting a socket, e.g. because `MAX_CONNECTIONS` was reached.
+    pub rejected: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub read_events: u64,
+    pub flush_events: u64,
+    /// Flushes that left data queued because the stream did not take all of it.
+    pub partial_writes: u64,
+    /// Sockets queued in the registry at the last flush.
+    pub registry_len: usize,
+    pub max_registry_len: usize,
+    pub tick_lag: Duration,
+}
+
+impl Metrics {
+    pub fn open_connections(&self) -> u64 {
+        self.accepted - self.closed
+    }
+
+    /// Renders the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let counters = [
+            ("accepted_total", self.accepted),
+            ("closed_total", self.closed),
+            ("rejected_total", self.rejected),
+            ("read_bytes_total", self.bytes_read),
+            ("written_bytes_total", self.bytes_written),
+            ("read_events_total", self.read_events),
+            ("flush_events_total", self.flush_events),
+            ("partial_writes_total", self.partial_writes),
+        ];
+        for (name, value) in counters {
+            let _result = write!(
+                text,
+                "# TYPE socket_server_{name} counter\nsocket_server_{name} {value}\n"
+            );
+        }
+        let gauges = [
+            ("open_connections", self.open_connections() as f64),
+            ("registry_len", self.registry_len as f64),
+            ("max_registry_len", self.max_registry_len as f64),
+            ("tick_lag_seconds", self.tick_lag.as_secs_f64()),
+        ];
+        for (name, value) in gauges {
+            let _result = write!(
+                text,
+                "# TYPE socket_server_{name} gauge\nsocket_server_{name} {value}\n"
+            );
+        }
+        text
+    }
+}
+
+const MAX_SCRAPES: usize = 4;
+const MAX_REQUEST_LEN: usize = 1024;
+
+struct Scrape {
+    stream: TcpStream,
+    request: Vec<u8>,
+}
+
+/// Answers every HTTP request with the current metrics, from within the event loop.
+pub(crate) struct MetricsEndpoint {
+    listener: TcpListener,
+    scrapes: [Option<Scrape>; MAX_SCRAPES],
+    token: usize,
+}
+
+impl MetricsEndpoint {
+    /// Uses `token` for the listener and the `MAX_SCRAPES` tokens below it for connections.
+    pub fn open<P: Poll<TcpListener>>(
+        poll: &mut P,
+        listener: std::net::TcpListener,
+        token: usize,
+    ) -> Result<Self, ()> {
+        listener.set_nonblocking(true).map_err(|_| ())?;
+        let mut listener = TcpListener::from_std(listener);
+        poll.open(&mut listener, token)?;
+        Ok(Self {
+            listener,
+            scrapes: Default::default(),
+            token,
+        })
+    }
+
+    pub fn owns(&self, token: usize) -> bool {
+        token <= self.token && self.token - token <= MAX_SCRAPES
+    }
+
+    pub fn ready<P: Poll<TcpStream>>(&mut self, poll: &mut P, token: usize, metrics: &Metrics) {
+        if token == self.token {
+            return self.accept(poll);
+        }
+        let index = self.token - token - 1;
+        let Some(scrape) = &mut self.scrapes[index] else {
+            return;
+        };
+        let mut buf = [0; MAX_REQUEST_LEN];
+        let complete = loop {
+            match scrape.stream.read(&mut buf) {
+                Ok(0) => break true,
+                Ok(read_len) => {
+                    scrape.request.extend_from_slice(&buf[..read_len]);
+                    if scrape.request.len() >= MAX_REQUEST_LEN {
+                        break true;
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break false,
+                Err(_err) => break true,
+            }
+        };
+        if !complete && !scrape.request.windows(4).any(|line| line == b"\r\n\r\n") {
+            return;
+        }
+        let body = metrics.to_prometheus();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _result = scrape.stream.write_all(response.as_bytes());
+        poll.close(&mut scrape.stream);
+        self.scrapes[index] = None;
+    }
+
+    fn accept<P: Poll<TcpStream>>(&mut self, poll: &mut P) {
+        while let Ok((mut stream, _addr)) = self.listener.accept() {
+            let Some(index) = self.scrapes.iter().position(Option::is_none) else {
+                continue;
+            };
+            if poll.open(&mut stream, self.token - index - 1).is_ok() {
+                let request = Vec::new();
+                self.scrapes[index] = Some(Scrape { stream, request });
+            }
+        }
+    }
+}
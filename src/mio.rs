@@ -1,14 +1,21 @@
-use std::{net::ToSocketAddrs, time::Duration};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, ToSocketAddrs, UdpSocket},
+    rc::Rc,
+    time::Duration,
+};
 
 use qcell::LCellOwner;
 
 use crate::{
+    datagram::{DatagramListener, DatagramSelector, UdpPeer, UdpPoll},
+    metrics::MetricsEndpoint,
     selector::{Poll, Selector},
     socket::{Registry, ServerSocketListener},
     tick_machine::TickMachine,
 };
 
-pub(self) struct MioPoll {
+pub(crate) struct MioPoll {
     mio_poll: mio::Poll,
     mio_registry: mio::Registry,
 }
@@ -35,6 +42,14 @@ impl<T: mio::event::Source> Poll<T> for MioPoll {
             .map_err(|_| ())
     }
 
+    fn reregister(&mut self, stream: &mut T, token: usize, readable: bool) -> Result<(), ()> {
+        if readable {
+            <Self as Poll<T>>::open(self, stream, token)
+        } else {
+            stream.deregister(&self.mio_registry).map_err(|_| ())
+        }
+    }
+
     fn close(&mut self, stream: &mut T) {
         let _result = stream.deregister(&self.mio_registry);
     }
@@ -51,16 +66,293 @@ where
     [(); T::READ_BUFFFER_LEN]:,
     [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
+{
+    let listener = TcpListener::bind(addr).unwrap();
+    listen_with(owner, server, listener, tick, Ok, (), None)
+}
+
+/// Like `listen`, on an already bound `listener`.
+pub fn listen_on<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    listener: TcpListener,
+    tick: Duration,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    listen_with(owner, server, listener, tick, Ok, (), None)
+}
+
+/// Like `listen`, also answering HTTP requests on `metrics_addr` with the listener's metrics in
+/// the Prometheus text format.
+pub fn listen_with_metrics<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    addr: impl ToSocketAddrs,
+    tick: Duration,
+    metrics_addr: impl ToSocketAddrs,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    let listener = TcpListener::bind(addr).unwrap();
+    let metrics_listener = TcpListener::bind(metrics_addr).unwrap();
+    listen_with(
+        owner,
+        server,
+        listener,
+        tick,
+        Ok,
+        (),
+        Some(metrics_listener),
+    )
+}
+
+/// Like `listen_with_metrics`, on already bound listeners.
+pub fn listen_on_with_metrics<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    listener: TcpListener,
+    tick: Duration,
+    metrics_listener: TcpListener,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    listen_with(
+        owner,
+        server,
+        listener,
+        tick,
+        Ok,
+        (),
+        Some(metrics_listener),
+    )
+}
+
+pub fn listen_with_datagram<'id, T, U>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    addr: impl ToSocketAddrs,
+    datagram_server: U,
+    datagram_addr: impl ToSocketAddrs,
+    tick: Duration,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    U: DatagramListener<'id>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+    [(); U::READ_BUFFFER_LEN]:,
+    [(); U::WRITE_BUFFER_LEN]:,
+    [(); U::MAX_QUEUED_DATAGRAMS]:,
+{
+    let udp_socket = UdpSocket::bind(datagram_addr).unwrap();
+    let listener = TcpListener::bind(addr).unwrap();
+    listen_on_with_datagram(owner, server, listener, datagram_server, udp_socket, tick)
+}
+
+/// Like `listen_with_datagram`, on an already bound `listener` and `udp_socket`.
+pub fn listen_on_with_datagram<'id, T, U>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    listener: TcpListener,
+    datagram_server: U,
+    udp_socket: UdpSocket,
+    tick: Duration,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    U: DatagramListener<'id>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+    [(); U::READ_BUFFFER_LEN]:,
+    [(); U::WRITE_BUFFER_LEN]:,
+    [(); U::MAX_QUEUED_DATAGRAMS]:,
+{
+    udp_socket.set_nonblocking(true).unwrap();
+    let udp_socket = mio::net::UdpSocket::from_std(udp_socket);
+    let datagram = DatagramSelector::new(datagram_server, owner, udp_socket);
+    listen_with(owner, server, listener, tick, Ok, datagram, None)
+}
+
+pub fn listen_udp<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    addr: impl ToSocketAddrs,
+    tick: Duration,
+    idle_timeout: Duration,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    let udp_socket = UdpSocket::bind(addr).unwrap();
+    listen_udp_on(owner, server, udp_socket, tick, idle_timeout)
+}
+
+/// Like `listen_udp`, on an already bound `udp_socket`.
+pub fn listen_udp_on<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    udp_socket: UdpSocket,
+    tick: Duration,
+    idle_timeout: Duration,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    let registry = owner.cell(Registry::new());
+    let mut selector = Selector::<_, _, UdpPeer>::new(server, owner, UdpPoll::default());
+    let mut poll = MioPoll::new();
+    const SOCKET_TOKEN: mio::Token = mio::Token(0);
+    let udp_socket = {
+        udp_socket.set_nonblocking(true).unwrap();
+        let mut udp_socket = mio::net::UdpSocket::from_std(udp_socket);
+        poll.open(&mut udp_socket, SOCKET_TOKEN.0).unwrap();
+        Rc::new(udp_socket)
+    };
+    let mut events = mio::Events::with_capacity(1);
+    let mut tick_machine = TickMachine::new(tick);
+    let mut datagram = vec![0u8; u16::MAX as usize];
+    let mut expired = Vec::with_capacity(T::MAX_CONNECTIONS);
+    let mut unread = Vec::with_capacity(T::MAX_CONNECTIONS);
+    loop {
+        poll.mio_poll
+            .poll(&mut events, Some(Duration::ZERO))
+            .unwrap();
+        tick_machine.tick(|| {
+            selector.tick(owner);
+            expired.extend(selector.poll.peers.values().copied().filter(|&token| {
+                let stream = unsafe { selector.streams.get_unchecked(token).assume_init_ref() };
+                stream.last_read().elapsed() >= idle_timeout
+            }));
+            for token in expired.drain(..) {
+                let socket = unsafe { selector.sockets.get_unchecked_mut(token) };
+                socket.register_close_event(owner);
+            }
+        });
+        selector.metrics.tick_lag = tick_machine.lag();
+        selector.flush_registry(owner, &registry);
+        unread.retain(|&(token, addr)| {
+            if selector.poll.peers.get(&addr) != Some(&token) {
+                return false;
+            }
+            selector.read(owner, token);
+            let stream = unsafe { selector.streams.get_unchecked(token).assume_init_ref() };
+            stream.has_inbound()
+        });
+        if events.is_empty() {
+            continue;
+        }
+        while let Ok((read_len, addr)) = udp_socket.recv_from(&mut datagram) {
+            if !selector.poll.peers.contains_key(&addr) {
+                let stream = UdpPeer::new(udp_socket.clone(), addr, T::READ_BUFFFER_LEN);
+                if selector.accept(owner, stream, addr, &registry).is_err() {
+                    continue;
+                }
+            }
+            let Some(&token) = selector.poll.peers.get(&addr) else {
+                continue;
+            };
+            let stream = unsafe { selector.streams.get_unchecked_mut(token).assume_init_mut() };
+            stream.receive(&datagram[..read_len]);
+            selector.read(owner, token);
+            let stream = unsafe { selector.streams.get_unchecked(token).assume_init_ref() };
+            if stream.has_inbound() && !unread.contains(&(token, addr)) {
+                unread.push((token, addr));
+            }
+        }
+    }
+}
+
+pub(crate) trait DatagramEndpoint<'id> {
+    fn open(&mut self, poll: &mut MioPoll, token: usize);
+    fn tick(&mut self, owner: &mut LCellOwner<'id>);
+    fn read(&mut self, owner: &mut LCellOwner<'id>);
+    fn flush_registry(&mut self, owner: &mut LCellOwner<'id>);
+}
+
+impl<'id> DatagramEndpoint<'id> for () {
+    fn open(&mut self, _poll: &mut MioPoll, _token: usize) {}
+    fn tick(&mut self, _owner: &mut LCellOwner<'id>) {}
+    fn read(&mut self, _owner: &mut LCellOwner<'id>) {}
+    fn flush_registry(&mut self, _owner: &mut LCellOwner<'id>) {}
+}
+
+impl<'id, U: DatagramListener<'id>> DatagramEndpoint<'id> for DatagramSelector<'id, U>
+where
+    [(); U::READ_BUFFFER_LEN]:,
+    [(); U::WRITE_BUFFER_LEN]:,
+    [(); U::MAX_QUEUED_DATAGRAMS]:,
+{
+    fn open(&mut self, poll: &mut MioPoll, token: usize) {
+        poll.open(&mut self.udp_socket, token).unwrap();
+    }
+
+    fn tick(&mut self, owner: &mut LCellOwner<'id>) {
+        DatagramSelector::tick(self, owner)
+    }
+
+    fn read(&mut self, owner: &mut LCellOwner<'id>) {
+        DatagramSelector::read(self, owner)
+    }
+
+    fn flush_registry(&mut self, owner: &mut LCellOwner<'id>) {
+        DatagramSelector::flush_registry(self, owner)
+    }
+}
+
+pub(crate) fn listen_with<'id, T, D, Stream, F>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    listener: TcpListener,
+    tick: Duration,
+    mut open_stream: F,
+    mut datagram: D,
+    metrics_listener: Option<TcpListener>,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    D: DatagramEndpoint<'id>,
+    Stream: Read + Write + mio::event::Source,
+    F: FnMut(mio::net::TcpStream) -> Result<Stream, ()>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
 {
     let registry = owner.cell(Registry::new());
-    let mut selector = Selector::<_, _, mio::net::TcpStream>::new(server, owner, MioPoll::new());
+    let mut selector = Selector::<_, _, Stream>::new(server, owner, MioPoll::new());
     const LISTENER_TOKEN: mio::Token = mio::Token(usize::MAX);
-    let addr = addr.to_socket_addrs().unwrap().next().unwrap();
+    const DATAGRAM_TOKEN: mio::Token = mio::Token(usize::MAX - 1);
+    const METRICS_TOKEN: mio::Token = mio::Token(usize::MAX - 2);
     let listener = {
-        let mut listener = mio::net::TcpListener::bind(addr).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let mut listener = mio::net::TcpListener::from_std(listener);
         selector.poll.open(&mut listener, LISTENER_TOKEN.0).unwrap();
         listener
     };
+    datagram.open(&mut selector.poll, DATAGRAM_TOKEN.0);
+    let mut metrics = metrics_listener.map(|metrics_listener| {
+        MetricsEndpoint::open(&mut selector.poll, metrics_listener, METRICS_TOKEN.0).unwrap()
+    });
     let mut events = mio::Events::with_capacity(T::MAX_CONNECTIONS);
     let mut tick_machine = TickMachine::new(tick);
     loop {
@@ -69,14 +361,28 @@ where
             .mio_poll
             .poll(&mut events, Some(Duration::ZERO))
             .unwrap();
-        tick_machine.tick(|| T::tick(&selector.server, owner));
+        tick_machine.tick(|| {
+            selector.tick(owner);
+            datagram.tick(owner);
+        });
+        selector.metrics.tick_lag = tick_machine.lag();
         selector.flush_registry(owner, &registry);
+        datagram.flush_registry(owner);
         for event in events.iter() {
             let token = event.token();
             if token == LISTENER_TOKEN {
-                if let Ok((stream, addr)) = listener.accept() {
-                    let _result = selector.accept(owner, stream, addr, &registry);
+                while let Ok((stream, addr)) = listener.accept() {
+                    match open_stream(stream) {
+                        Ok(stream) => {
+                            let _result = selector.accept(owner, stream, addr, &registry);
+                        }
+                        Err(()) => selector.metrics.rejected += 1,
+                    }
                 }
+            } else if token == DATAGRAM_TOKEN {
+                datagram.read(owner)
+            } else if let Some(metrics) = metrics.as_mut().filter(|metrics| metrics.owns(token.0)) {
+                metrics.ready(&mut selector.poll, token.0, &selector.metrics)
             } else {
                 selector.read(owner, token.0)
             }
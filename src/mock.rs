@@ -13,7 +13,7 @@ use crate::{
     tick_machine::TickMachine,
 };
 
-pub(self) struct MockPoll;
+struct MockPoll;
 
 impl<'id, T> Poll<MockStream<'id, T>> for MockPoll
 where
@@ -25,10 +25,19 @@ where
         Ok(())
     }
 
+    fn reregister(
+        &mut self,
+        _stream: &mut MockStream<'id, T>,
+        _token: usize,
+        _readable: bool,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
     fn close(&mut self, _stream: &mut MockStream<'id, T>) {}
 }
 
-pub(self) struct MockStream<'id, T>
+struct MockStream<'id, T>
 where
     T: ServerSocketListener<'id>,
     [(); T::READ_BUFFFER_LEN]:,
@@ -45,7 +54,16 @@ where
     [(); T::WRITE_BUFFER_LEN]:,
 {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        Read::read(&mut self.write_buf, buf)
+        if self.write_buf.remaining() == 0 {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        let pos = self.write_buf.pos();
+        let read_len = Read::read(&mut &self.write_buf.filled()[pos..], buf)?;
+        unsafe { *self.write_buf.pos_mut() = pos + read_len };
+        if self.write_buf.remaining() == 0 {
+            self.write_buf.clear();
+        }
+        Ok(read_len)
     }
 }
 
@@ -89,12 +107,8 @@ where
     }
 }
 
-pub fn run_mock<'id, T1: ServerSocketListener<'id>, T2: ServerSocketListener<'id>>(
-    owner: &mut LCellOwner<'id>,
-    server1: T1,
-    server2: T2,
-    tick: Duration,
-) where
+pub fn run_mock<'id, T1, T2>(owner: &mut LCellOwner<'id>, server1: T1, server2: T2, tick: Duration)
+where
     T1: ServerSocketListener<'id, Connection: Default>,
     [(); T1::MAX_CONNECTIONS]:,
     [(); T1::READ_BUFFFER_LEN]:,
@@ -128,9 +142,11 @@ pub fn run_mock<'id, T1: ServerSocketListener<'id>, T2: ServerSocketListener<'id
             break;
         }
         tick_machine.tick(|| {
-            T1::tick(&selector1.server, owner);
-            T2::tick(&selector2.server, owner);
+            selector1.tick(owner);
+            selector2.tick(owner);
         });
+        let stream1 = unsafe { selector1.streams.get_unchecked_mut(0).assume_init_mut() };
+        let stream2 = unsafe { selector2.streams.get_unchecked_mut(0).assume_init_mut() };
         MockStream::flex(stream1, stream2).unwrap();
         if stream1.write_buf.remaining() != 0 {
             selector1.read(owner, 0);
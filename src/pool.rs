@@ -0,0 +1,96 @@
+use std::ops::Deref;
+
+use fast_collections::Cursor;
+use qcell::{LCell, LCellOwner};
+
+pub(crate) type Chunk<'id, const N: usize> = Box<LCell<'id, Cursor<u8, N>>>;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum BufferStrategy {
+    /// Buffers are attached on accept and kept until the socket closes.
+    #[default]
+    Dedicated,
+    /// Buffers are attached while they hold pending data and returned to the pool when drained.
+    Pooled,
+}
+
+/// A socket buffer backed by a chunk borrowed from a [`BufferPool`].
+///
+/// The selector attaches the chunk before calling into the listener, so it is always present
+/// inside `ServerSocketListener` callbacks.
+pub struct Buffer<'id, const N: usize> {
+    chunk: Option<Chunk<'id, N>>,
+}
+
+impl<'id, const N: usize> Buffer<'id, N> {
+    pub const fn detached() -> Self {
+        Self { chunk: None }
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.chunk.is_some()
+    }
+}
+
+impl<'id, const N: usize> Default for Buffer<'id, N> {
+    fn default() -> Self {
+        Self::detached()
+    }
+}
+
+impl<'id, const N: usize> Deref for Buffer<'id, N> {
+    type Target = LCell<'id, Cursor<u8, N>>;
+
+    fn deref(&self) -> &Self::Target {
+        self.chunk
+            .as_deref()
+            .expect("socket buffer is not attached")
+    }
+}
+
+/// Free list of buffer chunks shared by every socket of a selector.
+pub(crate) struct BufferPool<'id, const N: usize> {
+    free: Vec<Chunk<'id, N>>,
+}
+
+impl<'id, const N: usize> BufferPool<'id, N> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            free: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn acquire(&mut self) -> Chunk<'id, N> {
+        self.free.pop().unwrap_or_default()
+    }
+
+    pub fn recycle(&mut self, mut chunk: Chunk<'id, N>) {
+        if self.free.len() < self.free.capacity() {
+            chunk.get_mut().clear();
+            self.free.push(chunk);
+        }
+    }
+
+    pub fn attach(&mut self, buffer: &mut Buffer<'id, N>) {
+        if buffer.chunk.is_none() {
+            buffer.chunk = Some(self.acquire());
+        }
+    }
+
+    pub fn release(&mut self, buffer: &mut Buffer<'id, N>) {
+        if let Some(chunk) = buffer.chunk.take() {
+            self.recycle(chunk);
+        }
+    }
+
+    /// Releases the chunk only if it holds no unread data.
+    pub fn release_drained(&mut self, owner: &mut LCellOwner<'id>, buffer: &mut Buffer<'id, N>) {
+        if buffer
+            .chunk
+            .as_ref()
+            .is_some_and(|chunk| chunk.ro(owner).remaining() == 0)
+        {
+            self.release(buffer);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use crate::socket::ConnectionId;
+
+/// Groups of connections keyed by `ServerSocketListener::RoomId`. Connections leave every room
+/// when they are closed.
+pub struct Rooms<K> {
+    members: HashMap<K, HashSet<ConnectionId>>,
+    joined: HashMap<ConnectionId, HashSet<K>>,
+}
+
+impl<K: Eq + Hash + Clone> Rooms<K> {
+    pub fn new() -> Self {
+        Self {
+            members: HashMap::new(),
+            joined: HashMap::new(),
+        }
+    }
+
+    /// Returns `false` if `id` was already in `room`.
+    pub fn join(&mut self, room: K, id: ConnectionId) -> bool {
+        if !self.joined.entry(id).or_default().insert(room.clone()) {
+            return false;
+        }
+        self.members.entry(room).or_default().insert(id)
+    }
+
+    /// Returns `false` if `id` was not in `room`.
+    pub fn leave(&mut self, room: &K, id: ConnectionId) -> bool {
+        let Some(members) = self.members.get_mut(room) else {
+            return false;
+        };
+        if !members.remove(&id) {
+            return false;
+        }
+        if members.is_empty() {
+            self.members.remove(room);
+        }
+        if let Some(rooms) = self.joined.get_mut(&id) {
+            rooms.remove(room);
+            if rooms.is_empty() {
+                self.joined.remove(&id);
+            }
+        }
+        true
+    }
+
+    pub fn leave_all(&mut self, id: ConnectionId) {
+        let Some(rooms) = self.joined.remove(&id) else {
+            return;
+        };
+        for room in rooms {
+            if let Some(members) = self.members.get_mut(&room) {
+                members.remove(&id);
+                if members.is_empty() {
+                    self.members.remove(&room);
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, room: &K, id: ConnectionId) -> bool {
+        self.members
+            .get(room)
+            .is_some_and(|members| members.contains(&id))
+    }
+
+    pub fn members(&self, room: &K) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.members.get(room).into_iter().flatten().copied()
+    }
+
+    pub fn rooms(&self, id: ConnectionId) -> impl Iterator<Item = &K> {
+        self.joined.get(&id).into_iter().flatten()
+    }
+}
+
+impl<K: Eq + Hash + Clone> Default for Rooms<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    io::{Read, Write},
+    io::{ErrorKind, Read, Write},
     mem::{transmute_copy, MaybeUninit},
     net::SocketAddr,
 };
@@ -7,10 +7,16 @@ use std::{
 use fast_collections::Slab;
 use qcell::{LCell, LCellOwner};
 
-use super::socket::{Registry, ServerSocketListener, Socket, SocketState};
+use super::{
+    framing::compact,
+    metrics::Metrics,
+    pool::{BufferPool, BufferStrategy},
+    socket::{ConnectionId, Registry, Request, ServerSocketListener, Socket, SocketState, Sockets},
+};
 
 pub(crate) trait Poll<T> {
     fn open(&mut self, stream: &mut T, token: usize) -> Result<(), ()>;
+    fn reregister(&mut self, stream: &mut T, token: usize, readable: bool) -> Result<(), ()>;
     fn close(&mut self, stream: &mut T);
 }
 
@@ -25,6 +31,11 @@ where
     pub server: LCell<'id, T>,
     pub sockets: Slab<Socket<'id, 'registry, T>, { T::MAX_CONNECTIONS }>,
     pub streams: [MaybeUninit<Stream>; T::MAX_CONNECTIONS],
+    alive: [bool; T::MAX_CONNECTIONS],
+    generations: [u32; T::MAX_CONNECTIONS],
+    read_pool: BufferPool<'id, { T::READ_BUFFFER_LEN }>,
+    write_pool: BufferPool<'id, { T::WRITE_BUFFER_LEN }>,
+    pub metrics: Metrics,
 }
 
 impl<'id, 'registry, T, P, Stream> Selector<'id, 'registry, T, P, Stream>
@@ -42,6 +53,89 @@ where
             sockets: Slab::new(),
             streams,
             poll,
+            alive: [false; T::MAX_CONNECTIONS],
+            generations: [0; T::MAX_CONNECTIONS],
+            read_pool: BufferPool::new(T::MAX_CONNECTIONS),
+            write_pool: BufferPool::new(T::MAX_CONNECTIONS),
+            metrics: Metrics::default(),
+        }
+    }
+
+    fn attach_buffers(&mut self, id: usize) {
+        let socket = unsafe { self.sockets.get_unchecked_mut(id) };
+        self.read_pool.attach(&mut socket.read_buf);
+        self.write_pool.attach(&mut socket.write_buf);
+    }
+
+    fn live_sockets(&mut self) -> Sockets<'id, 'registry, '_, T> {
+        Sockets {
+            sockets: &mut self.sockets,
+            alive: &self.alive,
+            read_pool: &mut self.read_pool,
+            write_pool: &mut self.write_pool,
+            metrics: &self.metrics,
+        }
+    }
+
+    fn apply_requests(
+        &mut self,
+        owner: &mut LCellOwner<'id>,
+        registry: &'registry LCell<'id, Registry<'id, T>>,
+    ) {
+        if registry.ro(owner).requests.is_empty() {
+            return;
+        }
+        let requests = std::mem::take(&mut registry.rw(owner).requests);
+        for request in requests {
+            match request {
+                Request::Send(id, payload) => self.write_to(owner, id, &payload),
+                Request::Close(id) => {
+                    if let Some(socket) = self.live_sockets().get_mut(id) {
+                        socket.register_close_event(owner);
+                        self.release_drained_buffers(owner, id.index());
+                    }
+                }
+                Request::Room(room, payload) => {
+                    let members = registry.ro(owner).rooms.members(&room).collect::<Vec<_>>();
+                    for id in members {
+                        self.write_to(owner, id, &payload);
+                    }
+                }
+                Request::Broadcast(filter, payload) => {
+                    self.live_sockets().broadcast(owner, filter, &payload);
+                    self.release_all_drained_buffers(owner);
+                }
+            }
+        }
+    }
+
+    fn write_to(&mut self, owner: &mut LCellOwner<'id>, id: ConnectionId, data: &[u8]) {
+        if let Some(socket) = self.live_sockets().get_mut(id) {
+            let _result = socket.write(owner, data);
+            self.release_drained_buffers(owner, id.index());
+        }
+    }
+
+    fn release_all_drained_buffers(&mut self, owner: &mut LCellOwner<'id>) {
+        for id in 0..T::MAX_CONNECTIONS {
+            if self.alive[id] {
+                self.release_drained_buffers(owner, id);
+            }
+        }
+    }
+
+    fn release_drained_buffers(&mut self, owner: &mut LCellOwner<'id>, id: usize) {
+        if T::BUFFER_STRATEGY != BufferStrategy::Pooled {
+            return;
+        }
+        let socket = unsafe { self.sockets.get_unchecked_mut(id) };
+        self.read_pool.release_drained(owner, &mut socket.read_buf);
+        if socket.state == SocketState::Idle {
+            self.write_pool
+                .release_drained(owner, &mut socket.write_buf);
+            if !socket.write_buf.is_attached() {
+                socket.write_gaps.clear();
+            }
         }
     }
 }
@@ -54,18 +148,72 @@ where
     [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
 {
+    pub fn tick(&mut self, owner: &mut LCellOwner<'id>) {
+        let mut sockets = Sockets {
+            sockets: &mut self.sockets,
+            alive: &self.alive,
+            read_pool: &mut self.read_pool,
+            write_pool: &mut self.write_pool,
+            metrics: &self.metrics,
+        };
+        T::tick(&self.server, owner, &mut sockets);
+        self.resume_full_reads(owner);
+        self.release_all_drained_buffers(owner);
+    }
+
     pub fn read(&mut self, owner: &mut LCellOwner<'id>, token: usize) {
+        self.metrics.read_events += 1;
+        if unsafe { self.sockets.get_unchecked(token) }.read_paused {
+            return;
+        }
+        self.attach_buffers(token);
+        self.read_attached(owner, token);
+        self.release_drained_buffers(owner, token);
+    }
+
+    /// Reads until the stream would block, as mio only reports readiness again once it has.
+    fn read_attached(&mut self, owner: &mut LCellOwner<'id>, token: usize) {
         let socket = unsafe { self.sockets.get_unchecked_mut(token) };
         let stream = unsafe { self.streams.get_unchecked_mut(token).assume_init_mut() };
-        match socket.read_buf.rw(owner).push_from_read(stream) {
-            Ok(read_len) => {
-                if read_len == 0 {
-                    socket.register_close_event(owner)
-                } else {
+        while !socket.read_paused && socket.state != SocketState::CloseRequest {
+            let read_buf = socket.read_buf.rw(owner);
+            compact(read_buf);
+            if read_buf.filled_len() == T::READ_BUFFFER_LEN {
+                socket.read_full = true;
+                return T::read_buffer_full(owner, &self.server, socket);
+            }
+            match read_buf.push_from_read(stream) {
+                Ok(read_len) => {
+                    self.metrics.bytes_read += read_len as u64;
+                    if read_len == 0 {
+                        return socket.register_close_event(owner);
+                    }
                     T::read(owner, &self.server, socket)
                 }
+                Err(io_err) if io_err.kind() == ErrorKind::WouldBlock => return,
+                Err(_io_err) => return socket.register_close_event(owner),
             }
-            Err(_io_err) => socket.register_close_event(owner),
+        }
+    }
+
+    /// Resumes sockets whose `read_buf` filled up once it has free space again, reading what
+    /// was left in the stream since no further readiness event will report it.
+    fn resume_full_reads(&mut self, owner: &mut LCellOwner<'id>) {
+        for id in 0..T::MAX_CONNECTIONS {
+            let socket = unsafe { self.sockets.get_unchecked_mut(id) };
+            if !self.alive[id] || !socket.read_full {
+                continue;
+            }
+            self.read_pool.attach(&mut socket.read_buf);
+            let read_buf = socket.read_buf.ro(owner);
+            if read_buf.pos() == 0 && read_buf.filled_len() == T::READ_BUFFFER_LEN {
+                continue;
+            }
+            socket.read_full = false;
+            socket.resume_reading(owner);
+            self.attach_buffers(id);
+            self.read_attached(owner, id);
+            self.release_drained_buffers(owner, id);
         }
     }
 
@@ -74,29 +222,79 @@ where
         owner: &mut LCellOwner<'id>,
         registry: &'registry LCell<'id, Registry<'id, T>>,
     ) {
+        self.apply_requests(owner, registry);
         let registry_vec_len = registry.ro(owner).len();
+        self.metrics.registry_len = registry_vec_len;
+        self.metrics.max_registry_len = self.metrics.max_registry_len.max(registry_vec_len);
+        let mut requeued_len = 0;
         for ind in 0..registry_vec_len {
-            let id = *unsafe { registry.ro(&owner).get_unchecked(ind) };
+            let id = *unsafe { registry.ro(owner).get_unchecked(ind) };
+            let mut requeue = false;
+            match unsafe { self.sockets.get_unchecked(id) }.state {
+                SocketState::Idle => {}
+                SocketState::WriteRequest | SocketState::Flushing => {
+                    self.attach_buffers(id);
+                    let socket = unsafe { self.sockets.get_unchecked_mut(id) };
+                    let stream = unsafe { self.streams.get_unchecked_mut(id).assume_init_mut() };
+                    if socket.state == SocketState::WriteRequest {
+                        self.metrics.flush_events += 1;
+                        T::flush(owner, &self.server, socket);
+                        if socket.write_queue_full {
+                            socket.write_queue_full = false;
+                            T::write_queue_full(owner, &self.server, socket);
+                        }
+                    }
+                    let pending_len = socket.pending_write_len(owner);
+                    let flushed = socket.flush_vectored(owner, stream);
+                    if flushed.is_ok() {
+                        let unsent_len = socket.pending_write_len(owner);
+                        self.metrics.bytes_written += (pending_len - unsent_len) as u64;
+                    }
+                    match flushed {
+                        Ok(_) if socket.state == SocketState::CloseRequest => {
+                            self.close(owner, id);
+                            continue;
+                        }
+                        Ok(true) => socket.state = SocketState::Idle,
+                        Ok(false) => {
+                            self.metrics.partial_writes += 1;
+                            socket.state = SocketState::Flushing;
+                            requeue = true;
+                        }
+                        Err(()) => {
+                            self.close(owner, id);
+                            continue;
+                        }
+                    }
+                }
+                SocketState::CloseRequest => {
+                    self.close(owner, id);
+                    continue;
+                }
+            }
             let socket = unsafe { self.sockets.get_unchecked_mut(id) };
             let stream = unsafe { self.streams.get_unchecked_mut(id).assume_init_mut() };
-            match socket.state {
-                SocketState::Idle => continue,
-                SocketState::WriteRequest => {
-                    socket.state = SocketState::Idle;
-                    T::flush(owner, &self.server, socket);
-                    match socket.write_buf.rw(owner).push_to_write(stream) {
-                        Ok(write_len) => {
-                            if write_len == 0 {
-                                self.close(owner, id)
-                            }
-                        }
-                        Err(_) => self.close(owner, id),
-                    };
+            if socket.read_interest == socket.read_paused {
+                let readable = !socket.read_paused;
+                if self
+                    .poll
+                    .reregister(stream, socket.token, readable)
+                    .is_err()
+                {
+                    self.close(owner, id);
+                    continue;
                 }
-                SocketState::CloseRequest => self.close(owner, id),
+                socket.read_interest = readable;
             }
+            if requeue {
+                unsafe { *registry.rw(owner).get_unchecked_mut(requeued_len) = id };
+                requeued_len += 1;
+                continue;
+            }
+            socket.queued = false;
+            self.release_drained_buffers(owner, id);
         }
-        registry.rw(owner).clear();
+        unsafe { *registry.rw(owner).len_mut() = requeued_len };
     }
 
     pub fn accept(
@@ -106,9 +304,16 @@ where
         addr: SocketAddr,
         registry: &'registry LCell<'id, Registry<'id, T>>,
     ) -> Result<(), ()> {
-        let id = self
+        let Ok(id) = self
             .sockets
-            .add_with_index(|ind| Socket::new(registry, *ind))?;
+            .add_with_index(|ind| Socket::new(registry, *ind, self.generations[*ind]))
+        else {
+            self.metrics.rejected += 1;
+            return Err(());
+        };
+        self.metrics.accepted += 1;
+        self.alive[id] = true;
+        registry.rw(owner).generations[id] = Some(self.generations[id]);
         let stream = unsafe { self.streams.get_unchecked_mut(id) };
         let socket = unsafe { self.sockets.get_unchecked_mut(id) };
         *stream = MaybeUninit::new(accepted_stream);
@@ -116,18 +321,33 @@ where
             .poll
             .open(unsafe { stream.assume_init_mut() }, socket.token)
         {
-            Ok(()) => T::accept(owner, &self.server, socket, addr),
+            Ok(()) => {
+                self.attach_buffers(id);
+                let socket = unsafe { self.sockets.get_unchecked_mut(id) };
+                T::accept(owner, &self.server, socket, addr);
+                self.release_drained_buffers(owner, id);
+            }
             Err(_err) => socket.register_close_event(owner),
         }
         Ok(())
     }
 
     pub fn close(&mut self, owner: &mut LCellOwner<'id>, id: usize) {
+        self.attach_buffers(id);
         let socket = unsafe { self.sockets.get_unchecked_mut(id) };
-        let stream = unsafe { self.streams.get_unchecked_mut(id).assume_init_mut() };
+        let stream = unsafe { self.streams.get_unchecked_mut(id) };
+        owner.rw(socket.registry).generations[id] = None;
         T::close(owner, &self.server, socket);
-        self.poll.close(stream);
+        owner.rw(socket.registry).rooms.leave_all(socket.id());
+        self.read_pool.release(&mut socket.read_buf);
+        self.write_pool.release(&mut socket.write_buf);
+        socket.recycle_overflow(owner);
+        self.poll.close(unsafe { stream.assume_init_mut() });
+        unsafe { stream.assume_init_drop() };
         let token = socket.token;
         unsafe { self.sockets.remove_unchecked(token) };
+        self.alive[token] = false;
+        self.metrics.closed += 1;
+        self.generations[token] = self.generations[token].wrapping_add(1);
     }
 }
@@ -1,7 +1,22 @@
 use derive_more::{Deref, DerefMut};
-use fast_collections::{Cursor, Vec};
+use fast_collections::{Cursor, Slab, Vec};
 use qcell::{LCell, LCellOwner};
-use std::net::SocketAddr;
+use std::{
+    collections::VecDeque,
+    hash::Hash,
+    io::{ErrorKind, IoSlice, Write},
+    iter,
+    net::SocketAddr,
+    ops::Range,
+};
+
+use crate::{
+    metrics::Metrics,
+    pool::{Buffer, BufferPool, BufferStrategy, Chunk},
+    room::Rooms,
+};
+
+const MAX_IO_SLICES: usize = 16;
 
 #[derive(Deref, DerefMut)]
 pub struct Socket<'id: 'registry, 'registry, T: ServerSocketListener<'id>>
@@ -10,13 +25,24 @@ where
     [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
 {
-    pub read_buf: LCell<'id, Cursor<u8, { T::READ_BUFFFER_LEN }>>,
-    pub write_buf: LCell<'id, Cursor<u8, { T::WRITE_BUFFER_LEN }>>,
+    pub read_buf: Buffer<'id, { T::READ_BUFFFER_LEN }>,
+    pub write_buf: Buffer<'id, { T::WRITE_BUFFER_LEN }>,
     #[deref]
     #[deref_mut]
     pub(crate) connection: T::Connection,
     pub(crate) state: SocketState,
+    pub(crate) queued: bool,
+    pub(crate) read_paused: bool,
+    pub(crate) read_interest: bool,
+    pub(crate) read_full: bool,
+    pub(crate) overflow: VecDeque<Chunk<'id, { T::WRITE_BUFFER_LEN }>>,
+    pub(crate) overflow_len: usize,
+    /// Ranges of `write_buf` left unused ahead of frame headers written in place, skipped when
+    /// flushing. A gap at the end is headroom for the next frame and survives the flush.
+    pub(crate) write_gaps: VecDeque<Range<usize>>,
+    pub(crate) write_queue_full: bool,
     pub(crate) token: usize,
+    pub(crate) generation: u32,
     pub(crate) registry: &'registry LCell<'id, Registry<'id, T>>,
 }
 
@@ -27,35 +53,254 @@ where
     [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
 {
-    pub fn new(registry: &'registry LCell<'id, Registry<'id, T>>, token: usize) -> Self {
+    pub fn new(
+        registry: &'registry LCell<'id, Registry<'id, T>>,
+        token: usize,
+        generation: u32,
+    ) -> Self {
         Self {
             read_buf: Default::default(),
             write_buf: Default::default(),
             connection: Default::default(),
             state: SocketState::default(),
+            queued: false,
+            read_paused: false,
+            read_interest: true,
+            read_full: false,
+            overflow: VecDeque::new(),
+            overflow_len: 0,
+            write_gaps: VecDeque::new(),
+            write_queue_full: false,
             token,
+            generation,
             registry,
         }
     }
 }
 
+/// Identifies a connection across ticks. Slots are reused after a close, so the generation
+/// tells a stale id apart from the connection that took its slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionId {
+    index: usize,
+    generation: u32,
+}
+
+impl ConnectionId {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Handle to the live sockets of a listener, passed to `ServerSocketListener::tick`.
+pub struct Sockets<'id: 'registry, 'registry, 'a, T: ServerSocketListener<'id>>
+where
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    pub(crate) sockets: &'a mut Slab<Socket<'id, 'registry, T>, { T::MAX_CONNECTIONS }>,
+    pub(crate) alive: &'a [bool; T::MAX_CONNECTIONS],
+    pub(crate) read_pool: &'a mut BufferPool<'id, { T::READ_BUFFFER_LEN }>,
+    pub(crate) write_pool: &'a mut BufferPool<'id, { T::WRITE_BUFFER_LEN }>,
+    pub(crate) metrics: &'a Metrics,
+}
+
+impl<'id: 'registry, 'registry, T> Sockets<'id, 'registry, '_, T>
+where
+    T: ServerSocketListener<'id>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    /// Returns the socket `id` refers to, or `None` if that connection has been closed.
+    pub fn get_mut(&mut self, id: ConnectionId) -> Option<&mut Socket<'id, 'registry, T>> {
+        if !*self.alive.get(id.index)? {
+            return None;
+        }
+        let socket = unsafe { self.sockets.get_unchecked_mut(id.index) };
+        if socket.generation != id.generation {
+            return None;
+        }
+        self.read_pool.attach(&mut socket.read_buf);
+        self.write_pool.attach(&mut socket.write_buf);
+        Some(socket)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Socket<'id, 'registry, T>> + '_ {
+        let sockets: *mut Slab<Socket<'id, 'registry, T>, { T::MAX_CONNECTIONS }> = self.sockets;
+        let (read_pool, write_pool) = (&mut *self.read_pool, &mut *self.write_pool);
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, &alive)| alive)
+            .map(move |(token, _)| {
+                // Every token is yielded once, so the returned sockets never alias.
+                let socket = unsafe { (*sockets).get_unchecked_mut(token) };
+                read_pool.attach(&mut socket.read_buf);
+                write_pool.attach(&mut socket.write_buf);
+                socket
+            })
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        self.metrics
+    }
+
+    /// Writes `payload` to every connection matching `filter` as with `Socket::write`.
+    pub fn broadcast<F>(&mut self, owner: &mut LCellOwner<'id>, filter: F, payload: &[u8])
+    where
+        F: Fn(ConnectionId, &T::Connection) -> bool,
+    {
+        for socket in self.iter_mut() {
+            if filter(socket.id(), &socket.connection) {
+                let _result = socket.write(owner, payload);
+            }
+        }
+    }
+
+    /// Registers a close event; the socket is closed on the next flush.
+    pub fn close(&mut self, owner: &mut LCellOwner<'id>, id: ConnectionId) -> Result<(), ()> {
+        let socket = self.get_mut(id).ok_or(())?;
+        socket.register_close_event(owner);
+        Ok(())
+    }
+}
+
 #[derive(Deref, DerefMut)]
 pub struct Registry<'id, T: ServerSocketListener<'id>>
 where
+    [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
 {
+    #[deref]
+    #[deref_mut]
     pub(crate) vec: Vec<usize, { T::MAX_CONNECTIONS }>,
+    pub(crate) overflow_pool: BufferPool<'id, { T::WRITE_BUFFER_LEN }>,
+    pub(crate) requests: std::vec::Vec<Request<T::RoomId, T::Connection>>,
+    pub(crate) rooms: Rooms<T::RoomId>,
+    /// Generation of the open connection in each slot, kept so rooms only admit live ids.
+    pub(crate) generations: [Option<u32>; T::MAX_CONNECTIONS],
+}
+
+pub(crate) type ConnectionFilter<C> = Box<dyn Fn(ConnectionId, &C) -> bool>;
+
+/// Applied in the order the `ServerHandle` calls were made.
+pub(crate) enum Request<K, C> {
+    Send(ConnectionId, Box<[u8]>),
+    Close(ConnectionId),
+    Room(K, Box<[u8]>),
+    Broadcast(ConnectionFilter<C>, Box<[u8]>),
+}
+
+/// Addresses any connection of the listener by id, applied before the next flush. Requests for
+/// connections that have been closed in the meantime are dropped.
+pub struct ServerHandle<'id, 'registry, T: ServerSocketListener<'id>>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    registry: &'registry LCell<'id, Registry<'id, T>>,
+}
+
+impl<'id, T: ServerSocketListener<'id>> Clone for ServerHandle<'id, '_, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'id, T: ServerSocketListener<'id>> Copy for ServerHandle<'id, '_, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+}
+
+impl<'id, T: ServerSocketListener<'id>> ServerHandle<'id, '_, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    /// Queues `data` to be written to `id` as with `Socket::write`.
+    pub fn send(&self, owner: &mut LCellOwner<'id>, id: ConnectionId, data: &[u8]) {
+        let request = Request::Send(id, data.into());
+        owner.rw(self.registry).requests.push(request);
+    }
+
+    /// Queues a close event for `id`.
+    pub fn close(&self, owner: &mut LCellOwner<'id>, id: ConnectionId) {
+        owner.rw(self.registry).requests.push(Request::Close(id));
+    }
+
+    /// Adds `id` to `room`. Returns `false` if `id` was already in `room` or its connection has
+    /// been closed.
+    pub fn join(&self, owner: &mut LCellOwner<'id>, room: T::RoomId, id: ConnectionId) -> bool {
+        let registry = owner.rw(self.registry);
+        registry.is_open(id) && registry.rooms.join(room, id)
+    }
+
+    pub fn leave(&self, owner: &mut LCellOwner<'id>, room: &T::RoomId, id: ConnectionId) -> bool {
+        owner.rw(self.registry).rooms.leave(room, id)
+    }
+
+    pub fn rooms<'a>(&'a self, owner: &'a LCellOwner<'id>) -> &'a Rooms<T::RoomId> {
+        &owner.ro(self.registry).rooms
+    }
+
+    /// Queues `payload` to be written to every connection matching `filter` as of the next flush.
+    pub fn broadcast<F>(&self, owner: &mut LCellOwner<'id>, filter: F, payload: &[u8])
+    where
+        F: Fn(ConnectionId, &T::Connection) -> bool + 'static,
+    {
+        let request = Request::Broadcast(Box::new(filter), payload.into());
+        owner.rw(self.registry).requests.push(request);
+    }
+
+    /// Queues `data` to be written to every member of `room` as of the next flush.
+    pub fn broadcast_to_room(&self, owner: &mut LCellOwner<'id>, room: T::RoomId, data: &[u8]) {
+        let request = Request::Room(room, data.into());
+        owner.rw(self.registry).requests.push(request);
+    }
 }
 
 impl<'id, T: ServerSocketListener<'id>> Registry<'id, T>
 where
+    [(); T::WRITE_BUFFER_LEN]:,
     [(); T::MAX_CONNECTIONS]:,
 {
     pub fn new() -> Self {
         Self {
             vec: Default::default(),
+            overflow_pool: BufferPool::new(T::MAX_CONNECTIONS),
+            requests: std::vec::Vec::new(),
+            rooms: Rooms::new(),
+            generations: [None; T::MAX_CONNECTIONS],
         }
     }
+
+    pub(crate) fn is_open(&self, id: ConnectionId) -> bool {
+        self.generations
+            .get(id.index)
+            .is_some_and(|&generation| generation == Some(id.generation))
+    }
+}
+
+impl<'id, T: ServerSocketListener<'id>> Default for Registry<'id, T>
+where
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -63,6 +308,8 @@ pub(crate) enum SocketState {
     #[default]
     Idle,
     WriteRequest,
+    /// A partial write is waiting for the stream; only the write is retried.
+    Flushing,
     CloseRequest,
 }
 
@@ -82,21 +329,288 @@ where
         self.state = SocketState::CloseRequest;
     }
 
+    /// Appends `data` to `write_buf`, spilling into the overflow queue once it is full, and
+    /// registers a flush. Fails without writing anything if the overflow queue would grow past
+    /// `WRITE_HIGH_WATER_MARK`. While the overflow queue is non-empty, bytes pushed straight into
+    /// `write_buf` would overtake it, so use this instead.
+    pub fn write(&mut self, owner: &mut LCellOwner<'id>, mut data: &[u8]) -> Result<(), ()> {
+        if self.overflow.is_empty() {
+            let write_buf = self.write_buf.rw(owner);
+            let free_len = T::WRITE_BUFFER_LEN - write_buf.filled_len();
+            if data.len() > free_len
+                && self.overflow_len + data.len() - free_len > T::WRITE_HIGH_WATER_MARK
+            {
+                return self.fail_write(owner);
+            }
+            let (head, tail) = data.split_at(free_len.min(data.len()));
+            write_buf.write_all(head).map_err(|_| ())?;
+            data = tail;
+        } else if self.overflow_len + data.len() > T::WRITE_HIGH_WATER_MARK {
+            return self.fail_write(owner);
+        }
+        while !data.is_empty() {
+            let tail_is_full = self
+                .overflow
+                .back()
+                .is_none_or(|chunk| chunk.ro(owner).filled_len() == T::WRITE_BUFFER_LEN);
+            if tail_is_full {
+                let chunk = owner.rw(self.registry).overflow_pool.acquire();
+                self.overflow.push_back(chunk);
+            }
+            let chunk = unsafe { self.overflow.back().unwrap_unchecked() }.rw(owner);
+            let len = (T::WRITE_BUFFER_LEN - chunk.filled_len()).min(data.len());
+            chunk.write_all(&data[..len]).map_err(|_| ())?;
+            self.overflow_len += len;
+            data = &data[len..];
+        }
+        self.register_flush_event(owner);
+        Ok(())
+    }
+
+    /// Like `write`, but encodes `message` straight into `write_buf`, or into a new overflow chunk
+    /// once `write_buf` is full or the overflow queue is non-empty.
+    pub(crate) fn write_with<E: Encode>(
+        &mut self,
+        owner: &mut LCellOwner<'id>,
+        message: &E,
+    ) -> Result<(), ()> {
+        if self.overflow.is_empty() {
+            let write_buf = self.write_buf.rw(owner);
+            let (filled_len, pos) = (write_buf.filled_len(), write_buf.pos());
+            if message.encode(write_buf).is_ok() {
+                self.register_flush_event(owner);
+                return Ok(());
+            }
+            unsafe {
+                *write_buf.filled_len_mut() = filled_len;
+                *write_buf.pos_mut() = pos;
+            }
+        }
+        let mut chunk = owner.rw(self.registry).overflow_pool.acquire();
+        let encoded = chunk.get_mut();
+        let result = message.encode(encoded);
+        let len = encoded.filled_len();
+        unsafe { *encoded.pos_mut() = 0 };
+        if result.is_err() || self.overflow_len + len > T::WRITE_HIGH_WATER_MARK {
+            owner.rw(self.registry).overflow_pool.recycle(chunk);
+            return match result {
+                Ok(()) => self.fail_write(owner),
+                Err(()) => Err(()),
+            };
+        }
+        self.overflow.push_back(chunk);
+        self.overflow_len += len;
+        self.register_flush_event(owner);
+        Ok(())
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        ConnectionId {
+            index: self.token,
+            generation: self.generation,
+        }
+    }
+
+    pub fn handle(&self) -> ServerHandle<'id, 'registry, T> {
+        ServerHandle {
+            registry: self.registry,
+        }
+    }
+
+    pub fn overflow_len(&self) -> usize {
+        self.overflow_len
+    }
+
+    /// Bytes waiting in `write_buf` and the overflow queue, not counting `write_gaps`.
+    pub(crate) fn pending_write_len(&self, owner: &LCellOwner<'id>) -> usize {
+        let filled_len = self.write_buf.ro(owner).filled_len();
+        let write_buf_len: usize = write_segments(&self.write_gaps, filled_len)
+            .map(|segment| segment.len())
+            .sum();
+        write_buf_len + self.overflow_len
+    }
+
+    /// Writes `write_buf` followed by the queued overflow chunks with a single vectored write,
+    /// returning whether everything was written and the stream flushed. `write_gaps` are left out,
+    /// and unwritten bytes of `write_buf` are shifted to its front, so they still go out ahead of
+    /// the overflow queue.
+    pub(crate) fn flush_vectored<W: Write>(
+        &mut self,
+        owner: &mut LCellOwner<'id>,
+        stream: &mut W,
+    ) -> Result<bool, ()> {
+        let write_buf = self.write_buf.ro(owner);
+        let filled_len = write_buf.filled_len();
+        let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+        let mut slices_len = 0;
+        let mut write_buf_len = 0;
+        for segment in write_segments(&self.write_gaps, filled_len) {
+            if slices_len == MAX_IO_SLICES {
+                break;
+            }
+            write_buf_len += segment.len();
+            slices[slices_len] = IoSlice::new(&write_buf.filled()[segment]);
+            slices_len += 1;
+        }
+        let segments_len = write_segments(&self.write_gaps, filled_len).count();
+        if slices_len == segments_len {
+            for chunk in self.overflow.iter().take(MAX_IO_SLICES - slices_len) {
+                let chunk = chunk.ro(owner);
+                slices[slices_len] = IoSlice::new(&chunk.filled()[chunk.pos()..]);
+                slices_len += 1;
+            }
+        }
+        let mut write_len = if slices_len == 0 {
+            0
+        } else {
+            match stream.write_vectored(&slices[..slices_len]) {
+                Ok(0) => return Err(()),
+                Ok(write_len) => write_len,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => 0,
+                Err(_err) => return Err(()),
+            }
+        };
+        let drained_len = write_len.min(write_buf_len);
+        if drained_len != 0 {
+            self.drain_write_buf(owner, drained_len);
+        }
+        write_len -= drained_len;
+        while write_len != 0 {
+            let chunk = unsafe { self.overflow.front().unwrap_unchecked() }.rw(owner);
+            let consumed = write_len.min(chunk.remaining());
+            unsafe { *chunk.pos_mut() += consumed };
+            self.overflow_len -= consumed;
+            write_len -= consumed;
+            if chunk.remaining() == 0 {
+                let chunk = unsafe { self.overflow.pop_front().unwrap_unchecked() };
+                owner.rw(self.registry).overflow_pool.recycle(chunk);
+            }
+        }
+        let flushed = match stream.flush() {
+            Ok(()) => true,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => false,
+            Err(_err) => return Err(()),
+        };
+        Ok(flushed && self.pending_write_len(owner) == 0)
+    }
+
+    /// Drops the first `drained_len` bytes of `write_buf` along with the gaps between them and
+    /// shifts the rest to its front, keeping a trailing gap as headroom.
+    fn drain_write_buf(&mut self, owner: &mut LCellOwner<'id>, mut drained_len: usize) {
+        let write_buf = self.write_buf.rw(owner);
+        let (filled_len, pos) = (write_buf.filled_len(), write_buf.pos());
+        let headroom = self
+            .write_gaps
+            .back()
+            .filter(|gap| gap.end >= filled_len)
+            .map(|gap| gap.start.min(filled_len));
+        let (mut kept_len, mut kept_pos) = (0, 0);
+        for segment in write_segments(&self.write_gaps, filled_len) {
+            let start = segment.start + drained_len.min(segment.len());
+            drained_len -= start - segment.start;
+            if pos > start {
+                kept_pos = kept_len + pos.min(segment.end) - start;
+            }
+            write_buf
+                .as_array()
+                .copy_within(start..segment.end, kept_len);
+            kept_len += segment.end - start;
+        }
+        self.write_gaps.clear();
+        if let Some(start) = headroom {
+            if pos > start {
+                kept_pos = kept_len + pos - start;
+            }
+            self.write_gaps
+                .push_back(kept_len..kept_len + filled_len - start);
+            kept_len += filled_len - start;
+        }
+        unsafe {
+            *write_buf.filled_len_mut() = kept_len;
+            *write_buf.pos_mut() = kept_pos;
+        }
+    }
+
+    pub(crate) fn recycle_overflow(&mut self, owner: &mut LCellOwner<'id>) {
+        let pool = &mut owner.rw(self.registry).overflow_pool;
+        for chunk in self.overflow.drain(..) {
+            pool.recycle(chunk);
+        }
+        self.overflow_len = 0;
+    }
+
+    fn fail_write(&mut self, owner: &mut LCellOwner<'id>) -> Result<(), ()> {
+        self.write_queue_full = true;
+        self.register_flush_event(owner);
+        Err(())
+    }
+
+    pub fn pause_reading(&mut self, owner: &mut LCellOwner<'id>) {
+        if !self.read_paused {
+            self.read_paused = true;
+            self.register_event(owner);
+        }
+    }
+
+    pub fn resume_reading(&mut self, owner: &mut LCellOwner<'id>) {
+        if self.read_paused {
+            self.read_paused = false;
+            self.register_event(owner);
+        }
+    }
+
+    pub fn is_reading_paused(&self) -> bool {
+        self.read_paused
+    }
+
     pub(self) fn register_event(&mut self, owner: &mut LCellOwner<'id>) {
-        if self.state == SocketState::Idle {
+        if !self.queued {
+            self.queued = true;
             let registry = owner.rw(self.registry);
             unsafe { registry.push_unchecked(self.token) };
         }
     }
 }
 
+/// Ranges of the first `filled_len` bytes of a write buffer outside of `gaps`.
+fn write_segments(
+    gaps: &VecDeque<Range<usize>>,
+    filled_len: usize,
+) -> impl Iterator<Item = Range<usize>> + '_ {
+    let starts = iter::once(0).chain(gaps.iter().map(move |gap| gap.end.min(filled_len)));
+    let ends = gaps
+        .iter()
+        .map(move |gap| gap.start.min(filled_len))
+        .chain([filled_len]);
+    starts
+        .zip(ends)
+        .filter(|(start, end)| start < end)
+        .map(|(start, end)| start..end)
+}
+
+/// A message `Socket::write_with` can encode into any write buffer.
+pub(crate) trait Encode {
+    fn encode<const N: usize>(&self, write_buf: &mut Cursor<u8, N>) -> Result<(), ()>;
+}
+
 pub trait ServerSocketListener<'id>: Sized {
     const MAX_CONNECTIONS: usize;
     const READ_BUFFFER_LEN: usize;
     const WRITE_BUFFER_LEN: usize;
+    const BUFFER_STRATEGY: BufferStrategy = BufferStrategy::Dedicated;
+    /// Bytes `Socket::write` may queue beyond `write_buf`; zero disables the overflow queue.
+    const WRITE_HIGH_WATER_MARK: usize = 0;
     type Connection;
+    type RoomId: Eq + Hash + Clone = ();
 
-    fn tick(server: &LCell<'id, Self>, owner: &mut LCellOwner<'id>);
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:;
 
     fn accept(
         owner: &mut LCellOwner<'id>,
@@ -117,6 +631,20 @@ pub trait ServerSocketListener<'id>: Sized {
         [(); Self::WRITE_BUFFER_LEN]:,
         [(); Self::MAX_CONNECTIONS]:;
 
+    /// Called instead of reading when `read_buf` has no free space left after compaction. Pauses
+    /// reading by default; it resumes on the first tick that finds free space in `read_buf`.
+    fn read_buffer_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        connection.pause_reading(owner);
+    }
+
     fn flush(
         owner: &mut LCellOwner<'id>,
         server: &LCell<'id, Self>,
@@ -126,6 +654,20 @@ pub trait ServerSocketListener<'id>: Sized {
         [(); Self::WRITE_BUFFER_LEN]:,
         [(); Self::MAX_CONNECTIONS]:;
 
+    /// Called on the next flush after `Socket::write` hit `WRITE_HIGH_WATER_MARK`. Closes the
+    /// connection by default.
+    fn write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        connection.register_close_event(owner);
+    }
+
     fn close(
         owner: &mut LCellOwner<'id>,
         server: &LCell<'id, Self>,
@@ -4,6 +4,7 @@ pub struct TickMachine {
     start: Instant,
     last_tick: Duration,
     tick: Duration,
+    lag: Duration,
 }
 
 impl TickMachine {
@@ -12,14 +13,22 @@ impl TickMachine {
             start: Instant::now(),
             last_tick: Duration::ZERO,
             tick,
+            lag: Duration::ZERO,
         }
     }
 
+    /// How far behind its schedule the last tick ran.
+    pub fn lag(&self) -> Duration {
+        self.lag
+    }
+
     pub fn tick<F>(&mut self, f: F)
     where
         F: FnOnce(),
     {
-        if self.start.elapsed() - self.last_tick >= self.tick {
+        let elapsed = self.start.elapsed() - self.last_tick;
+        if elapsed >= self.tick {
+            self.lag = elapsed - self.tick;
             f();
             self.last_tick += self.tick;
         }
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{self, ErrorKind, IoSlice, Read, Write},
+    net::ToSocketAddrs,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use mio::net::TcpStream;
+use qcell::LCellOwner;
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ConfigBuilder, ServerConfig, ServerConnection, WantsVerifier,
+};
+
+use crate::{mio::listen_with, socket::ServerSocketListener};
+
+pub struct TlsStream {
+    stream: TcpStream,
+    connection: ServerConnection,
+}
+
+impl TlsStream {
+    pub fn new(stream: TcpStream, config: Arc<ServerConfig>) -> Result<Self, ()> {
+        let connection = ServerConnection::new(config).map_err(|_| ())?;
+        Ok(Self { stream, connection })
+    }
+
+    fn write_tls(&mut self) -> io::Result<()> {
+        while self.connection.wants_write() {
+            match self.connection.write_tls(&mut self.stream) {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.connection.wants_read() {
+            match self.connection.read_tls(&mut self.stream) {
+                Ok(0) => break,
+                Ok(_) => {
+                    if let Err(err) = self.connection.process_new_packets() {
+                        let _result = self.write_tls();
+                        return Err(io::Error::new(ErrorKind::InvalidData, err));
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        self.write_tls()?;
+        self.connection.reader().read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write_len = self.connection.writer().write(buf)?;
+        self.write_tls()?;
+        would_block_if_empty(write_len, buf.is_empty())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let write_len = self.connection.writer().write_vectored(bufs)?;
+        self.write_tls()?;
+        would_block_if_empty(write_len, bufs.iter().all(|buf| buf.is_empty()))
+    }
+
+    /// Fails with `WouldBlock` while encrypted bytes are still waiting for the socket.
+    fn flush(&mut self) -> io::Result<()> {
+        self.connection.writer().flush()?;
+        self.write_tls()?;
+        if self.connection.wants_write() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        Ok(())
+    }
+}
+
+/// rustls accepts no plaintext once its send buffer is full, which would read as a closed stream.
+fn would_block_if_empty(write_len: usize, input_is_empty: bool) -> io::Result<usize> {
+    if write_len == 0 && !input_is_empty {
+        return Err(ErrorKind::WouldBlock.into());
+    }
+    Ok(write_len)
+}
+
+impl mio::event::Source for TlsStream {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        self.stream.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        self.stream.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        self.stream.deregister(registry)
+    }
+}
+
+pub fn load_server_config(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> Result<Arc<ServerConfig>, ()> {
+    let (certs, key) = load_pem(cert_path, key_path)?;
+    let config = server_config_builder()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|_| ())?;
+    Ok(Arc::new(config))
+}
+
+pub fn load_certified_key(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> Result<Arc<CertifiedKey>, ()> {
+    let (certs, key) = load_pem(cert_path, key_path)?;
+    let key = ring::sign::any_supported_type(&key).map_err(|_| ())?;
+    Ok(Arc::new(CertifiedKey::new(certs, key)))
+}
+
+fn load_pem(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), ()> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .map_err(|_| ())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ())?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|_| ())?;
+    Ok((certs, key))
+}
+
+fn server_config_builder() -> Result<ConfigBuilder<ServerConfig, WantsVerifier>, ()> {
+    ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|_| ())
+}
+
+#[derive(Default, Clone)]
+pub struct CertificateSet {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl CertificateSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, server_name: &str, key: Arc<CertifiedKey>) {
+        self.by_name.insert(server_name.to_ascii_lowercase(), key);
+    }
+
+    pub fn set_default(&mut self, key: Arc<CertifiedKey>) {
+        self.default = Some(key);
+    }
+
+    pub fn get(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        server_name
+            .and_then(|server_name| self.by_name.get(&server_name.to_ascii_lowercase()))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+pub struct SniResolver {
+    certificates: RwLock<Arc<CertificateSet>>,
+}
+
+impl SniResolver {
+    pub fn new(certificates: CertificateSet) -> Arc<Self> {
+        Arc::new(Self {
+            certificates: RwLock::new(Arc::new(certificates)),
+        })
+    }
+
+    pub fn replace(&self, certificates: CertificateSet) {
+        *self.certificates.write().unwrap() = Arc::new(certificates);
+    }
+
+    pub fn certificates(&self) -> Arc<CertificateSet> {
+        self.certificates.read().unwrap().clone()
+    }
+
+    pub fn server_config(self: &Arc<Self>) -> Result<Arc<ServerConfig>, ()> {
+        let config = server_config_builder()?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        Ok(Arc::new(config))
+    }
+}
+
+impl Debug for SniResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SniResolver").finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.certificates().get(client_hello.server_name())
+    }
+}
+
+pub fn listen_tls<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    addr: impl ToSocketAddrs,
+    tick: Duration,
+    config: Arc<ServerConfig>,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    let listener = std::net::TcpListener::bind(addr).unwrap();
+    listen_tls_on(owner, server, listener, tick, config)
+}
+
+/// Like `listen_tls`, on an already bound `listener`.
+pub fn listen_tls_on<'id, T>(
+    owner: &mut LCellOwner<'id>,
+    server: T,
+    listener: std::net::TcpListener,
+    tick: Duration,
+    config: Arc<ServerConfig>,
+) -> !
+where
+    T: ServerSocketListener<'id, Connection: Default>,
+    [(); T::READ_BUFFFER_LEN]:,
+    [(); T::WRITE_BUFFER_LEN]:,
+    [(); T::MAX_CONNECTIONS]:,
+{
+    listen_with(
+        owner,
+        server,
+        listener,
+        tick,
+        |stream| TlsStream::new(stream, config.clone()),
+        (),
+        None,
+    )
+}
@@ -1,139 +1,383 @@
+use std::{borrow::Cow, collections::VecDeque, io::Write, ops::Range};
+
 use fast_collections::Cursor;
-use httparse::{Request, EMPTY_HEADER};
+use httparse::{Header, Request, Status, EMPTY_HEADER};
 use qcell::{LCell, LCellOwner};
 use sha1::{Digest, Sha1};
 
+use crate::socket::Encode;
+
+#[cfg(feature = "websocket-deflate")]
+mod deflate;
+#[cfg(feature = "websocket-deflate")]
+use deflate::Deflate;
+#[cfg(feature = "websocket-deflate")]
+pub use deflate::DeflateConfig;
+
+mod client;
+mod frame;
+mod listener;
+pub use client::*;
+#[cfg(feature = "websocket-deflate")]
+use frame::RSV1;
+use frame::{
+    frame_in_place, push_frame, read_frame, release_frame_header, reserve_frame_header, Fragment,
+    FIN,
+};
+pub use frame::{
+    Message, Opcode, WebSocketPolicy, CLOSE_INVALID_PAYLOAD, CLOSE_MESSAGE_TOO_BIG, CLOSE_NORMAL,
+    CLOSE_PROTOCOL_ERROR,
+};
+pub use listener::*;
+
 pub enum ReadError {
     NotFullRead,
     FlushRequest,
     CloseRequest,
 }
-#[derive(Default, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum WebSocketState {
     #[default]
     Idle,
     HandShaked,
     Accepted,
+    Closing,
+    Connecting,
 }
 
-pub fn websocket_read<'id, const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
-    owner: &mut LCellOwner<'id>,
-    websocket: &LCell<'id, WebSocketState>,
-    read_buf: &LCell<'id, Cursor<u8, { READ_BUFFFER_LEN }>>,
-    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
-) -> Result<(), ReadError> {
-    match websocket.ro(owner) {
-        WebSocketState::Idle => {
-            let headers = {
-                let mut headers = [EMPTY_HEADER; 16];
-                let mut request = Request::new(&mut headers);
-                request
-                    .parse(read_buf.ro(owner).filled())
-                    .map_err(|_| ReadError::CloseRequest)?;
-                headers
-            };
-            let key = {
-                let key = headers
-                    .iter()
-                    .find(|e| e.name == "Sec-WebSocket-Key")
-                    .ok_or_else(|| ReadError::CloseRequest)?;
-                const WS_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
-                let mut sha1 = Sha1::default();
-                sha1.update(&key.value);
-                sha1.update(WS_GUID);
-                data_encoding::BASE64.encode(&sha1.finalize())
-            };
+pub struct HandshakeRequest<'a> {
+    pub path: &'a str,
+    pub origin: Option<&'a str>,
+    pub headers: &'a [Header<'a>],
+}
+
+impl<'a> HandshakeRequest<'a> {
+    pub fn header(&self, name: &str) -> Option<&'a [u8]> {
+        self.headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value)
+    }
 
-            let dst = unsafe { write_buf.rw(owner).unfilled_mut() };
+    pub fn protocols(&self) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case("Sec-WebSocket-Protocol"))
+            .filter_map(|header| std::str::from_utf8(header.value).ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|protocol| !protocol.is_empty())
+    }
+}
 
-            const PREFIX: &[u8; 94] = b"HTTP/1.1 101 Switching Protocols\nUpgrade: websocket\nConnection: Upgrade\nSec-WebSocket-Accept: ";
-            const SUFFIX: &[u8; 12] = b"\r\n   \r\n \r\n\r\n";
+pub enum HandshakeResponse<'a> {
+    Accept {
+        protocol: Option<&'a str>,
+        headers: &'a [(&'a str, &'a str)],
+    },
+    Reject {
+        status: u16,
+    },
+}
 
-            const KEY_INDEX: usize = PREFIX.len();
-            let key_last_index = KEY_INDEX + key.len();
+#[derive(Clone, Copy)]
+pub struct WebSocketConfig {
+    pub policy: WebSocketPolicy,
+    #[cfg(feature = "websocket-deflate")]
+    pub deflate: Option<DeflateConfig>,
+}
 
-            dst[..KEY_INDEX].copy_from_slice(PREFIX);
-            dst[KEY_INDEX..key_last_index].copy_from_slice(key.as_bytes());
-            dst[key_last_index..key_last_index + SUFFIX.len()].copy_from_slice(SUFFIX);
+impl WebSocketConfig {
+    pub const DEFAULT: Self = Self {
+        policy: WebSocketPolicy::DEFAULT,
+        #[cfg(feature = "websocket-deflate")]
+        deflate: None,
+    };
+}
 
-            unsafe {
-                *write_buf.rw(owner).filled_len_mut() = write_buf
-                    .ro(owner)
-                    .filled()
-                    .len()
-                    .unchecked_add(key_last_index.unchecked_add(SUFFIX.len()))
-            };
-            read_buf.rw(owner).clear();
-            *websocket.rw(owner) = WebSocketState::HandShaked;
-            Err(ReadError::FlushRequest)
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Default)]
+pub struct WebSocket {
+    pub state: WebSocketState,
+    pub(crate) client_key: Option<[u8; CLIENT_KEY_LEN]>,
+    pub(crate) fragment: Option<Fragment>,
+    #[cfg(feature = "websocket-deflate")]
+    pub(crate) deflate: Option<Deflate>,
+}
+
+impl WebSocket {
+    #[cfg(feature = "websocket-deflate")]
+    pub fn is_deflate_negotiated(&self) -> bool {
+        self.deflate.is_some()
+    }
+}
+
+const CLIENT_KEY_LEN: usize = 24;
+
+pub fn websocket_read<'id, F, const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    read_buf: &LCell<'id, Cursor<u8, { READ_BUFFFER_LEN }>>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    config: &WebSocketConfig,
+    handshake: F,
+) -> Result<Message, ReadError>
+where
+    F: for<'a> FnOnce(&HandshakeRequest<'a>) -> HandshakeResponse<'a>,
+{
+    match websocket.ro(owner).state {
+        WebSocketState::Idle => {
+            let (websocket, read_buf, write_buf) = owner.rw3(websocket, read_buf, write_buf);
+            read_handshake(websocket, read_buf, write_buf, config, handshake)
+        }
+        WebSocketState::HandShaked | WebSocketState::Closing | WebSocketState::Connecting => {
+            Err(ReadError::CloseRequest)
         }
-        WebSocketState::HandShaked => Err(ReadError::CloseRequest),
         WebSocketState::Accepted => {
-            let frame_header: u16 = *read_buf
-                .rw(owner)
-                .read_transmute()
-                .ok_or_else(|| ReadError::NotFullRead)?;
-            let (header_byte1, header_byte2): (u8, u8) =
-                unsafe { fast_collections::const_transmute_unchecked(frame_header) };
-            let opcode = header_byte1 & 0b0000_1111;
-            if opcode != 2 {
-                return Err(ReadError::CloseRequest);
-            }
-            let mask = header_byte2 & 0b1000_0000;
-            let payload_length = header_byte2 & 127;
-            const MASK_KEY_LEN: usize = 4;
-            if mask != 0 {
-                let masking_key = *read_buf
-                    .rw(owner)
-                    .read_transmute::<[u8; MASK_KEY_LEN]>()
-                    .ok_or_else(|| ReadError::NotFullRead)?;
-                let mut mask_i = 0;
-                let read_cursor_pos = read_buf.ro(owner).pos();
-                for i in read_cursor_pos..read_cursor_pos + payload_length as usize {
-                    unsafe {
-                        *read_buf.rw(owner).get_unchecked_mut(i) =
-                            read_buf.ro(owner).get_unchecked(i) ^ masking_key[mask_i]
-                    };
-                    mask_i += 1;
-                    mask_i %= MASK_KEY_LEN;
-                }
-            }
-            Ok(())
+            let (websocket, read_buf, write_buf) = owner.rw3(websocket, read_buf, write_buf);
+            read_frame(websocket, read_buf, write_buf, &config.policy)
         }
     }
 }
 
+fn read_handshake<F, const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
+    websocket: &mut WebSocket,
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    #[cfg_attr(not(feature = "websocket-deflate"), allow(unused_variables))]
+    config: &WebSocketConfig,
+    handshake: F,
+) -> Result<Message, ReadError>
+where
+    F: for<'a> FnOnce(&HandshakeRequest<'a>) -> HandshakeResponse<'a>,
+{
+    let mut headers = [EMPTY_HEADER; 16];
+    let mut request = Request::new(&mut headers);
+    match request.parse(read_buf.filled()) {
+        Ok(Status::Complete(_)) => {}
+        Ok(Status::Partial) => return Err(ReadError::NotFullRead),
+        Err(_) => return Err(ReadError::CloseRequest),
+    }
+    let path = request.path.unwrap_or("/");
+    let headers = request.headers;
+    let key = headers
+        .iter()
+        .find(|e| e.name.eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .ok_or(ReadError::CloseRequest)?;
+    let key = accept_key(key.value);
+    let origin = headers
+        .iter()
+        .find(|e| e.name.eq_ignore_ascii_case("Origin"))
+        .and_then(|e| std::str::from_utf8(e.value).ok());
+    let request = HandshakeRequest {
+        path,
+        origin,
+        headers,
+    };
+    let response = match handshake(&request) {
+        HandshakeResponse::Accept {
+            protocol: Some(protocol),
+            ..
+        } if !request.protocols().any(|offered| offered == protocol) => {
+            HandshakeResponse::Reject { status: 400 }
+        }
+        response => response,
+    };
+    websocket.state = match response {
+        HandshakeResponse::Accept { protocol, headers } => {
+            #[cfg(feature = "websocket-deflate")]
+            let extensions = {
+                let deflate = config
+                    .deflate
+                    .and_then(|deflate| deflate.negotiate(request.headers));
+                websocket.deflate = deflate.map(Deflate::new);
+                deflate.map(|deflate| deflate.response_header())
+            };
+            #[cfg(not(feature = "websocket-deflate"))]
+            let extensions: Option<String> = None;
+            write_handshake_accept(write_buf, &key, protocol, extensions.as_deref(), headers)
+                .map_err(|_| ReadError::CloseRequest)?;
+            WebSocketState::HandShaked
+        }
+        HandshakeResponse::Reject { status } => {
+            write_handshake_reject(write_buf, status).map_err(|_| ReadError::CloseRequest)?;
+            unsafe { *write_buf.pos_mut() = write_buf.filled_len() };
+            WebSocketState::Closing
+        }
+    };
+    read_buf.clear();
+    Err(ReadError::FlushRequest)
+}
+
+fn accept_key(key: &[u8]) -> String {
+    const WS_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+    let mut sha1 = Sha1::default();
+    sha1.update(key);
+    sha1.update(WS_GUID);
+    data_encoding::BASE64.encode(&sha1.finalize())
+}
+
+/// Frames the bytes written straight into `write_buf` since the last flush as one binary message.
+/// Their header length is only known now, so they are moved once to make room for it; use
+/// `websocket_send` to write the header ahead of the payload without moving it.
 pub fn websocket_flush<'id, const WRITE_BUFFER_LEN: usize>(
     owner: &mut LCellOwner<'id>,
-    websocket: &LCell<'id, WebSocketState>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+) -> Result<(), ()> {
+    flush_frames(owner, websocket, write_buf, &mut VecDeque::new())
+}
+
+/// Like `websocket_flush`, but writes the header into the headroom `reserve_frame_header` left
+/// in `gaps`, so the bytes after it are framed without moving them.
+pub(crate) fn flush_frames<'id, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
     write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    gaps: &mut VecDeque<Range<usize>>,
 ) -> Result<(), ()> {
-    if *websocket.ro(owner) == WebSocketState::HandShaked {
-        *websocket.rw(owner) = WebSocketState::Accepted;
-    } else {
-        let mut buffer = Cursor::<u8, { WRITE_BUFFER_LEN }>::new();
-        let mut write_buf = write_buf.rw(owner);
-        let payload = &mut write_buf;
-        {
-            let header0: u8 = 2;
-            buffer.push(header0).map_err(|_| ())?;
+    let (websocket, write_buf) = owner.rw2(websocket, write_buf);
+    match websocket.state {
+        WebSocketState::HandShaked => {
+            websocket.state = WebSocketState::Accepted;
+            unsafe { *write_buf.pos_mut() = write_buf.filled_len() };
+            return Ok(());
         }
-        let payload_len = payload.filled_len() - payload.pos();
-        if payload_len >= 8 * 8 * 8 {
-            let header1: [u8; 8] =
-                unsafe { fast_collections::const_transmute_unchecked(payload_len) };
-            buffer.push_transmute(header1).map_err(|_| ())?;
-        } else if payload_len >= 126 {
-            let header1: [u8; 2] =
-                unsafe { fast_collections::const_transmute_unchecked(payload_len) };
-            buffer.push_transmute(header1).map_err(|_| ())?;
-        } else {
-            let header1: u8 = payload_len as u8;
-            buffer.push(header1).map_err(|_| ())?;
+        WebSocketState::Closing => {
+            unsafe { *write_buf.filled_len_mut() = write_buf.pos() };
+            return Err(());
         }
-        buffer.push_from_cursor(payload)?;
-        write_buf.clear();
-        write_buf.push_from_cursor(&mut buffer)?;
+        WebSocketState::Connecting => return Err(()),
+        WebSocketState::Idle | WebSocketState::Accepted => {}
+    }
+    if write_buf.pos() == write_buf.filled_len() {
+        release_frame_header(write_buf, gaps);
+        return Ok(());
+    }
+    #[cfg(feature = "websocket-deflate")]
+    if let Some(deflate) = websocket.deflate.as_mut() {
+        let pos = write_buf.pos();
+        let mut compressed = Vec::new();
+        deflate.compress(&write_buf.filled()[pos..], &mut compressed)?;
+        unsafe { *write_buf.filled_len_mut() = pos };
+        write_buf.write_all(&compressed).map_err(|_| ())?;
+        return frame_in_place(write_buf, FIN | RSV1 | Opcode::Binary as u8, gaps);
     }
-    Ok(())
+    frame_in_place(write_buf, FIN | Opcode::Binary as u8, gaps)
+}
+
+/// Leaves headroom for the header of the next bytes written straight into `write_buf`.
+pub(crate) fn websocket_reserve_header<'id, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    gaps: &mut VecDeque<Range<usize>>,
+) {
+    let (websocket, write_buf) = owner.rw2(websocket, write_buf);
+    if websocket.state == WebSocketState::Accepted {
+        reserve_frame_header(write_buf, gaps);
+    }
+}
+
+/// Writes `payload` as one binary message. Once the connection is accepted the frame header is
+/// written directly ahead of the payload; otherwise the payload is left for `websocket_flush`.
+pub fn websocket_send<'id, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    payload: &[u8],
+) -> Result<(), ()> {
+    match websocket_message(owner, websocket, write_buf, &mut VecDeque::new(), payload)? {
+        Some(frame) => frame.encode(write_buf.rw(owner)),
+        None => write_buf.rw(owner).write_all(payload).map_err(|_| ()),
+    }
+}
+
+/// A binary message ready to be framed, its payload compressed if deflate was negotiated.
+pub(crate) struct OutgoingFrame<'a> {
+    header0: u8,
+    payload: Cow<'a, [u8]>,
+}
+
+impl Encode for OutgoingFrame<'_> {
+    fn encode<const N: usize>(&self, write_buf: &mut Cursor<u8, N>) -> Result<(), ()> {
+        push_frame(write_buf, self.header0, &self.payload, None)
+    }
+}
+
+/// Frames any bytes already pending in `write_buf` and returns the next binary message for
+/// `payload`. Returns `None` until the connection is accepted.
+pub(crate) fn websocket_message<'id, 'a, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    gaps: &mut VecDeque<Range<usize>>,
+    payload: &'a [u8],
+) -> Result<Option<OutgoingFrame<'a>>, ()> {
+    if websocket.ro(owner).state != WebSocketState::Accepted {
+        return Ok(None);
+    }
+    flush_frames(owner, websocket, write_buf, gaps)?;
+    #[cfg(feature = "websocket-deflate")]
+    if let Some(deflate) = websocket.rw(owner).deflate.as_mut() {
+        let mut compressed = Vec::new();
+        deflate.compress(payload, &mut compressed)?;
+        return Ok(Some(OutgoingFrame {
+            header0: FIN | RSV1 | Opcode::Binary as u8,
+            payload: Cow::Owned(compressed),
+        }));
+    }
+    Ok(Some(OutgoingFrame {
+        header0: FIN | Opcode::Binary as u8,
+        payload: Cow::Borrowed(payload),
+    }))
+}
+
+fn write_handshake_accept<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    key: &str,
+    protocol: Option<&str>,
+    extensions: Option<&str>,
+    headers: &[(&str, &str)],
+) -> std::io::Result<()> {
+    write!(
+        write_buf,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {key}\r\n"
+    )?;
+    if let Some(protocol) = protocol {
+        write!(write_buf, "Sec-WebSocket-Protocol: {protocol}\r\n")?;
+    }
+    if let Some(extensions) = extensions {
+        write!(write_buf, "Sec-WebSocket-Extensions: {extensions}\r\n")?;
+    }
+    for (name, value) in headers {
+        write!(write_buf, "{name}: {value}\r\n")?;
+    }
+    write_buf.write_all(b"\r\n")
+}
+
+fn write_handshake_reject<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    status: u16,
+) -> std::io::Result<()> {
+    let reason = match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Error",
+    };
+    write!(
+        write_buf,
+        "HTTP/1.1 {status} {reason}\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+    )
 }
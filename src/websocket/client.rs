@@ -0,0 +1,117 @@
+use std::io::Write;
+
+use fast_collections::Cursor;
+use httparse::{Response, Status, EMPTY_HEADER};
+use qcell::{LCell, LCellOwner};
+
+use super::{
+    accept_key,
+    frame::{read_frame, write_frame, FIN, MASK_KEY_LEN},
+    Message, Opcode, ReadError, WebSocket, WebSocketPolicy, WebSocketState, CLIENT_KEY_LEN,
+};
+
+pub fn websocket_connect<'id, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+    host: &str,
+    path: &str,
+    protocols: &[&str],
+) -> Result<(), ()> {
+    let (websocket, write_buf) = owner.rw2(websocket, write_buf);
+    if websocket.state != WebSocketState::Idle {
+        return Err(());
+    }
+    let mut key = [0u8; CLIENT_KEY_LEN];
+    data_encoding::BASE64.encode_mut(&rand::random::<[u8; 16]>(), &mut key);
+    let key_str = std::str::from_utf8(&key).unwrap();
+    write!(
+        write_buf,
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key_str}\r\nSec-WebSocket-Version: 13\r\n"
+    )
+    .map_err(|_| ())?;
+    if !protocols.is_empty() {
+        write!(
+            write_buf,
+            "Sec-WebSocket-Protocol: {}\r\n",
+            protocols.join(", ")
+        )
+        .map_err(|_| ())?;
+    }
+    write_buf.write_all(b"\r\n").map_err(|_| ())?;
+    websocket.client_key = Some(key);
+    websocket.state = WebSocketState::Connecting;
+    Ok(())
+}
+
+pub fn websocket_client_read<'id, const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    read_buf: &LCell<'id, Cursor<u8, { READ_BUFFFER_LEN }>>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+) -> Result<Message, ReadError> {
+    let (websocket, read_buf, write_buf) = owner.rw3(websocket, read_buf, write_buf);
+    match websocket.state {
+        WebSocketState::Connecting => {
+            let expected_key = accept_key(&websocket.client_key.ok_or(ReadError::CloseRequest)?);
+            let response_len = {
+                let mut headers = [EMPTY_HEADER; 16];
+                let mut response = Response::new(&mut headers);
+                let response_len = match response.parse(read_buf.filled()) {
+                    Ok(Status::Complete(response_len)) => response_len,
+                    Ok(Status::Partial) => return Err(ReadError::NotFullRead),
+                    Err(_) => return Err(ReadError::CloseRequest),
+                };
+                if response.code != Some(101) {
+                    return Err(ReadError::CloseRequest);
+                }
+                let accepted = response.headers.iter().any(|header| {
+                    header.name.eq_ignore_ascii_case("Sec-WebSocket-Accept")
+                        && header.value == expected_key.as_bytes()
+                });
+                if !accepted {
+                    return Err(ReadError::CloseRequest);
+                }
+                response_len
+            };
+            websocket.state = WebSocketState::Accepted;
+            unsafe { *read_buf.pos_mut() = response_len };
+            if read_buf.remaining() == 0 {
+                read_buf.clear();
+                return Err(ReadError::NotFullRead);
+            }
+            read_frame(websocket, read_buf, write_buf, &WebSocketPolicy::CLIENT)
+        }
+        WebSocketState::Accepted => {
+            read_frame(websocket, read_buf, write_buf, &WebSocketPolicy::CLIENT)
+        }
+        WebSocketState::Idle | WebSocketState::HandShaked | WebSocketState::Closing => {
+            Err(ReadError::CloseRequest)
+        }
+    }
+}
+
+pub fn websocket_client_flush<'id, const WRITE_BUFFER_LEN: usize>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    write_buf: &LCell<'id, Cursor<u8, { WRITE_BUFFER_LEN }>>,
+) -> Result<(), ()> {
+    let (websocket, write_buf) = owner.rw2(websocket, write_buf);
+    match websocket.state {
+        WebSocketState::Connecting => {
+            unsafe { *write_buf.pos_mut() = write_buf.filled_len() };
+            Ok(())
+        }
+        WebSocketState::Accepted if write_buf.pos() == write_buf.filled_len() => Ok(()),
+        WebSocketState::Accepted => write_frame(
+            write_buf,
+            FIN | Opcode::Binary as u8,
+            Some(rand::random::<[u8; MASK_KEY_LEN]>()),
+        ),
+        WebSocketState::Closing => {
+            unsafe { *write_buf.filled_len_mut() = write_buf.pos() };
+            Err(())
+        }
+        WebSocketState::Idle | WebSocketState::HandShaked => Err(()),
+    }
+}
@@ -0,0 +1,147 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use httparse::Header;
+
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+const MAX_WINDOW_BITS: u8 = 15;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct DeflateConfig {
+    pub server_no_context_takeover: bool,
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateConfig {
+    pub const DEFAULT: Self = Self {
+        server_no_context_takeover: false,
+        client_no_context_takeover: false,
+    };
+
+    pub(crate) fn negotiate(&self, headers: &[Header]) -> Option<Self> {
+        headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case("Sec-WebSocket-Extensions"))
+            .filter_map(|header| std::str::from_utf8(header.value).ok())
+            .flat_map(|value| value.split(','))
+            .find_map(|offer| self.accept_offer(offer))
+    }
+
+    fn accept_offer(&self, offer: &str) -> Option<Self> {
+        let mut params = offer.split(';').map(str::trim);
+        if params.next()? != "permessage-deflate" {
+            return None;
+        }
+        let mut negotiated = *self;
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            match (name, value) {
+                ("server_no_context_takeover", None) => {
+                    negotiated.server_no_context_takeover = true
+                }
+                ("client_no_context_takeover", None) => {
+                    negotiated.client_no_context_takeover = true
+                }
+                ("server_max_window_bits", Some(bits)) => {
+                    if bits.parse::<u8>().ok()? != MAX_WINDOW_BITS {
+                        return None;
+                    }
+                }
+                ("client_max_window_bits", _) => {}
+                _ => return None,
+            }
+        }
+        Some(negotiated)
+    }
+
+    pub(crate) fn response_header(&self) -> String {
+        let mut value = String::from("permessage-deflate");
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        value
+    }
+}
+
+pub(crate) enum DecompressError {
+    Invalid,
+    TooLarge,
+}
+
+pub(crate) struct Deflate {
+    config: DeflateConfig,
+    compress: Compress,
+    decompress: Decompress,
+}
+
+impl Deflate {
+    pub fn new(config: DeflateConfig) -> Self {
+        Self {
+            config,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+        }
+    }
+
+    pub fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), ()> {
+        let mut offset = 0;
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(input.len() - offset + 64);
+            }
+            let total_in = self.compress.total_in();
+            self.compress
+                .compress_vec(&input[offset..], output, FlushCompress::Sync)
+                .map_err(|_| ())?;
+            offset += (self.compress.total_in() - total_in) as usize;
+            if offset == input.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+        if output.ends_with(&DEFLATE_TAIL) {
+            output.truncate(output.len() - DEFLATE_TAIL.len());
+        }
+        if self.config.server_no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(())
+    }
+
+    pub fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<(), DecompressError> {
+        for input in [input, &DEFLATE_TAIL] {
+            let mut offset = 0;
+            loop {
+                if output.len() == output.capacity() {
+                    if output.len() >= limit {
+                        return Err(DecompressError::TooLarge);
+                    }
+                    output.reserve((input.len() * 4).max(64));
+                }
+                let total_in = self.decompress.total_in();
+                self.decompress
+                    .decompress_vec(&input[offset..], output, FlushDecompress::Sync)
+                    .map_err(|_| DecompressError::Invalid)?;
+                offset += (self.decompress.total_in() - total_in) as usize;
+                if offset == input.len() && output.len() < output.capacity() {
+                    break;
+                }
+            }
+        }
+        if output.len() > limit {
+            return Err(DecompressError::TooLarge);
+        }
+        if self.config.client_no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(())
+    }
+}
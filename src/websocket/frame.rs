@@ -0,0 +1,557 @@
+use std::{collections::VecDeque, io::Write, ops::Range};
+
+use fast_collections::Cursor;
+
+#[cfg(feature = "websocket-deflate")]
+use super::deflate::DecompressError;
+use super::{ReadError, WebSocket, WebSocketState};
+
+pub(crate) const FIN: u8 = 0b1000_0000;
+pub(crate) const RSV1: u8 = 0b0100_0000;
+const RSV2_RSV3: u8 = 0b0011_0000;
+const MASK: u8 = 0b1000_0000;
+pub(crate) const MASK_KEY_LEN: usize = 4;
+const MAX_CONTROL_PAYLOAD_LEN: usize = 125;
+const MAX_CONTROL_FRAME_LEN: usize = 2 + MASK_KEY_LEN + MAX_CONTROL_PAYLOAD_LEN;
+const MAX_FRAME_HEADER_LEN: usize = 10 + MASK_KEY_LEN;
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_PAYLOAD: u16 = 1007;
+pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Opcode {
+    Continuation = 0,
+    Text = 1,
+    Binary = 2,
+    Close = 8,
+    Ping = 9,
+    Pong = 10,
+}
+
+impl Opcode {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Continuation,
+            1 => Self::Text,
+            2 => Self::Binary,
+            8 => Self::Close,
+            9 => Self::Ping,
+            10 => Self::Pong,
+            _ => return None,
+        })
+    }
+
+    pub fn is_control(self) -> bool {
+        self as u8 & 0b1000 != 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Message {
+    pub opcode: Opcode,
+    pub len: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WebSocketPolicy {
+    pub require_mask: bool,
+    pub max_frame_size: usize,
+    pub max_message_size: usize,
+    pub allowed_opcodes: &'static [Opcode],
+}
+
+impl WebSocketPolicy {
+    pub const DEFAULT: Self = Self {
+        require_mask: true,
+        max_frame_size: usize::MAX,
+        max_message_size: usize::MAX,
+        allowed_opcodes: &[Opcode::Binary, Opcode::Close, Opcode::Ping, Opcode::Pong],
+    };
+
+    pub(crate) const CLIENT: Self = Self {
+        require_mask: false,
+        ..Self::DEFAULT
+    };
+}
+
+impl Default for WebSocketPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Fragment {
+    opcode: Opcode,
+    len: usize,
+    compressed: bool,
+}
+
+struct FrameHeader {
+    fin: bool,
+    compressed: bool,
+    opcode: Opcode,
+    masking_key: Option<[u8; MASK_KEY_LEN]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+enum FrameError {
+    Read(ReadError),
+    Close(u16),
+}
+
+impl From<ReadError> for FrameError {
+    fn from(value: ReadError) -> Self {
+        Self::Read(value)
+    }
+}
+
+fn parse_frame_header(frame: &[u8]) -> Result<Option<FrameHeader>, FrameError> {
+    let [header_byte1, header_byte2, ..] = *frame else {
+        return Ok(None);
+    };
+    if header_byte1 & RSV2_RSV3 != 0 {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+    }
+    let opcode = Opcode::from_u8(header_byte1 & 0b0000_1111)
+        .ok_or(FrameError::Close(CLOSE_PROTOCOL_ERROR))?;
+    let (payload_len, mut header_len) = match header_byte2 & 127 {
+        126 => match frame.get(2..4) {
+            Some(len) => (u16::from_be_bytes(len.try_into().unwrap()) as usize, 4),
+            None => return Ok(None),
+        },
+        127 => match frame.get(2..10) {
+            Some(len) => (u64::from_be_bytes(len.try_into().unwrap()) as usize, 10),
+            None => return Ok(None),
+        },
+        len => (len as usize, 2),
+    };
+    let masking_key = if header_byte2 & MASK != 0 {
+        let Some(masking_key) = frame.get(header_len..header_len + MASK_KEY_LEN) else {
+            return Ok(None);
+        };
+        header_len += MASK_KEY_LEN;
+        Some(masking_key.try_into().unwrap())
+    } else {
+        None
+    };
+    Ok(Some(FrameHeader {
+        fin: header_byte1 & FIN != 0,
+        compressed: header_byte1 & RSV1 != 0,
+        opcode,
+        masking_key,
+        header_len,
+        payload_len,
+    }))
+}
+
+pub(crate) fn read_frame<const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
+    websocket: &mut WebSocket,
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    policy: &WebSocketPolicy,
+) -> Result<Message, ReadError> {
+    loop {
+        match next_frame(websocket, read_buf, write_buf, policy) {
+            Ok(Some(message)) => return Ok(message),
+            Ok(None) => continue,
+            Err(FrameError::Read(err)) => return Err(err),
+            Err(FrameError::Close(code)) => {
+                close_frame(websocket, write_buf, code)?;
+                return Err(ReadError::FlushRequest);
+            }
+        }
+    }
+}
+
+fn next_frame<const READ_BUFFFER_LEN: usize, const WRITE_BUFFER_LEN: usize>(
+    websocket: &mut WebSocket,
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    policy: &WebSocketPolicy,
+) -> Result<Option<Message>, FrameError> {
+    let collected = websocket.fragment.map_or(0, |fragment| fragment.len);
+    let frame_pos = read_buf.pos() + collected;
+    let header =
+        parse_frame_header(&read_buf.filled()[frame_pos..])?.ok_or(ReadError::NotFullRead)?;
+    if policy.require_mask && header.masking_key.is_none() {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+    }
+    if header.opcode.is_control() {
+        if !header.fin || header.compressed || header.payload_len > MAX_CONTROL_PAYLOAD_LEN {
+            return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+        }
+    } else if header.opcode == Opcode::Continuation {
+        if websocket.fragment.is_none() || header.compressed {
+            return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+        }
+    } else if websocket.fragment.is_some() {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+    }
+    if header.opcode != Opcode::Continuation && !policy.allowed_opcodes.contains(&header.opcode) {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+    }
+    if header.compressed && !is_deflate_negotiated(websocket) {
+        return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR));
+    }
+    if header.payload_len > policy.max_frame_size
+        || (!header.opcode.is_control()
+            && collected.saturating_add(header.payload_len) > policy.max_message_size)
+        || frame_pos
+            .saturating_add(header.header_len)
+            .saturating_add(header.payload_len)
+            > READ_BUFFFER_LEN
+    {
+        return Err(FrameError::Close(CLOSE_MESSAGE_TOO_BIG));
+    }
+    let frame_len = header.header_len + header.payload_len;
+    if read_buf.filled_len() - frame_pos < frame_len {
+        return Err(ReadError::NotFullRead.into());
+    }
+    let payload_pos = frame_pos + header.header_len;
+    let payload_end = payload_pos + header.payload_len;
+    if let Some(masking_key) = header.masking_key {
+        let payload = unsafe { &mut read_buf.filled_mut()[payload_pos..payload_end] };
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= masking_key[i % MASK_KEY_LEN];
+        }
+    }
+    match header.opcode {
+        Opcode::Close => {
+            let code = match read_buf.filled()[payload_pos..payload_end] {
+                [] => CLOSE_NORMAL,
+                [_] => return Err(FrameError::Close(CLOSE_PROTOCOL_ERROR)),
+                [byte1, byte2, ..] => u16::from_be_bytes([byte1, byte2]),
+            };
+            close_frame(websocket, write_buf, code)?;
+            Err(ReadError::FlushRequest.into())
+        }
+        Opcode::Ping => {
+            let mut payload = [0u8; MAX_CONTROL_PAYLOAD_LEN];
+            let payload = &mut payload[..header.payload_len];
+            payload.copy_from_slice(&read_buf.filled()[payload_pos..payload_end]);
+            remove_range(read_buf, frame_pos, frame_len);
+            insert_control_frame(websocket, write_buf, Opcode::Pong, payload)
+                .map_err(|_| ReadError::CloseRequest)?;
+            Err(ReadError::FlushRequest.into())
+        }
+        Opcode::Pong => {
+            remove_range(read_buf, frame_pos, frame_len);
+            Ok(None)
+        }
+        _ if header.fin && websocket.fragment.is_none() => {
+            unsafe { *read_buf.pos_mut() = payload_pos };
+            complete_message(
+                websocket,
+                read_buf,
+                header.opcode,
+                header.payload_len,
+                header.compressed,
+                policy,
+            )
+            .map(Some)
+        }
+        _ => {
+            remove_range(read_buf, frame_pos, header.header_len);
+            let fragment = websocket.fragment.get_or_insert(Fragment {
+                opcode: header.opcode,
+                len: 0,
+                compressed: header.compressed,
+            });
+            fragment.len += header.payload_len;
+            if !header.fin {
+                return Ok(None);
+            }
+            let fragment = websocket.fragment.take().unwrap();
+            complete_message(
+                websocket,
+                read_buf,
+                fragment.opcode,
+                fragment.len,
+                fragment.compressed,
+                policy,
+            )
+            .map(Some)
+        }
+    }
+}
+
+fn complete_message<const READ_BUFFFER_LEN: usize>(
+    #[cfg_attr(not(feature = "websocket-deflate"), allow(unused_variables))]
+    websocket: &mut WebSocket,
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    opcode: Opcode,
+    len: usize,
+    compressed: bool,
+    policy: &WebSocketPolicy,
+) -> Result<Message, FrameError> {
+    #[cfg(feature = "websocket-deflate")]
+    let len = if compressed {
+        inflate_payload(websocket, read_buf, len, policy.max_message_size)?
+    } else {
+        len
+    };
+    #[cfg(not(feature = "websocket-deflate"))]
+    let _ = compressed;
+    if len > policy.max_message_size {
+        return Err(FrameError::Close(CLOSE_MESSAGE_TOO_BIG));
+    }
+    if opcode == Opcode::Text {
+        let pos = read_buf.pos();
+        std::str::from_utf8(&read_buf.filled()[pos..pos + len])
+            .map_err(|_| FrameError::Close(CLOSE_INVALID_PAYLOAD))?;
+    }
+    Ok(Message { opcode, len })
+}
+
+fn is_deflate_negotiated(#[allow(unused_variables)] websocket: &WebSocket) -> bool {
+    #[cfg(feature = "websocket-deflate")]
+    return websocket.deflate.is_some();
+    #[cfg(not(feature = "websocket-deflate"))]
+    false
+}
+
+#[cfg(feature = "websocket-deflate")]
+fn inflate_payload<const READ_BUFFFER_LEN: usize>(
+    websocket: &mut WebSocket,
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    payload_len: usize,
+    max_message_size: usize,
+) -> Result<usize, FrameError> {
+    let deflate = websocket
+        .deflate
+        .as_mut()
+        .ok_or(FrameError::Close(CLOSE_PROTOCOL_ERROR))?;
+    let payload_pos = read_buf.pos();
+    let payload_end = payload_pos + payload_len;
+    let filled_len = read_buf.filled_len();
+    let limit = (READ_BUFFFER_LEN - (filled_len - payload_len)).min(max_message_size);
+    let mut inflated = Vec::new();
+    deflate
+        .decompress(
+            &read_buf.filled()[payload_pos..payload_end],
+            &mut inflated,
+            limit,
+        )
+        .map_err(|err| match err {
+            DecompressError::Invalid => FrameError::Close(CLOSE_INVALID_PAYLOAD),
+            DecompressError::TooLarge => FrameError::Close(CLOSE_MESSAGE_TOO_BIG),
+        })?;
+    let inflated_end = payload_pos + inflated.len();
+    let buffer = read_buf.as_array();
+    buffer.copy_within(payload_end..filled_len, inflated_end);
+    buffer[payload_pos..inflated_end].copy_from_slice(&inflated);
+    unsafe { *read_buf.filled_len_mut() = inflated_end + (filled_len - payload_end) };
+    Ok(inflated.len())
+}
+
+fn remove_range<const READ_BUFFFER_LEN: usize>(
+    read_buf: &mut Cursor<u8, READ_BUFFFER_LEN>,
+    pos: usize,
+    len: usize,
+) {
+    let filled_len = read_buf.filled_len();
+    read_buf.as_array().copy_within(pos + len..filled_len, pos);
+    unsafe { *read_buf.filled_len_mut() = filled_len - len };
+}
+
+fn close_frame<const WRITE_BUFFER_LEN: usize>(
+    websocket: &mut WebSocket,
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    code: u16,
+) -> Result<(), ReadError> {
+    insert_control_frame(websocket, write_buf, Opcode::Close, &code.to_be_bytes())
+        .map_err(|_| ReadError::CloseRequest)?;
+    websocket.state = WebSocketState::Closing;
+    websocket.fragment = None;
+    Ok(())
+}
+
+/// Writes a control frame ahead of the unframed bytes after `pos`, moving them once.
+fn insert_control_frame<const WRITE_BUFFER_LEN: usize>(
+    websocket: &WebSocket,
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    opcode: Opcode,
+    payload: &[u8],
+) -> Result<(), ()> {
+    let mut frame = Cursor::<u8, MAX_CONTROL_FRAME_LEN>::new();
+    let masking_key = websocket.client_key.map(|_| rand::random());
+    push_frame_header(&mut frame, FIN | opcode as u8, payload.len(), masking_key)?;
+    let payload_pos = frame.filled_len();
+    frame.write_all(payload).map_err(|_| ())?;
+    mask_payload(
+        unsafe { &mut frame.filled_mut()[payload_pos..] },
+        masking_key,
+    );
+    let pos = write_buf.pos();
+    let filled_len = write_buf.filled_len();
+    let frame_len = frame.filled_len();
+    if filled_len + frame_len > WRITE_BUFFER_LEN {
+        return Err(());
+    }
+    let buffer = write_buf.as_array();
+    buffer.copy_within(pos..filled_len, pos + frame_len);
+    buffer[pos..pos + frame_len].copy_from_slice(frame.filled());
+    unsafe {
+        *write_buf.filled_len_mut() = filled_len + frame_len;
+        *write_buf.pos_mut() = pos + frame_len;
+    }
+    Ok(())
+}
+
+/// Frames the unframed bytes after `pos` in place, moving them once to make room for the header.
+pub(crate) fn write_frame<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    header0: u8,
+    masking_key: Option<[u8; MASK_KEY_LEN]>,
+) -> Result<(), ()> {
+    let pos = write_buf.pos();
+    let filled_len = write_buf.filled_len();
+    let mut header = Cursor::<u8, MAX_FRAME_HEADER_LEN>::new();
+    push_frame_header(&mut header, header0, filled_len - pos, masking_key)?;
+    let header_len = header.filled_len();
+    if filled_len + header_len > WRITE_BUFFER_LEN {
+        return Err(());
+    }
+    let frame_end = filled_len + header_len;
+    let buffer = write_buf.as_array();
+    buffer.copy_within(pos..filled_len, pos + header_len);
+    buffer[pos..pos + header_len].copy_from_slice(header.filled());
+    mask_payload(&mut buffer[pos + header_len..frame_end], masking_key);
+    unsafe {
+        *write_buf.filled_len_mut() = frame_end;
+        *write_buf.pos_mut() = frame_end;
+    }
+    Ok(())
+}
+
+/// Leaves `MAX_FRAME_HEADER_LEN` bytes of headroom at the end of `write_buf` as a gap, so the
+/// bytes written after it can be framed by `frame_in_place` without moving them.
+pub(crate) fn reserve_frame_header<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    gaps: &mut VecDeque<Range<usize>>,
+) {
+    let filled_len = write_buf.filled_len();
+    if write_buf.pos() != filled_len
+        || has_frame_header_room(write_buf, gaps)
+        || filled_len + MAX_FRAME_HEADER_LEN > WRITE_BUFFER_LEN
+    {
+        return;
+    }
+    gaps.push_back(filled_len..filled_len + MAX_FRAME_HEADER_LEN);
+    unsafe {
+        *write_buf.filled_len_mut() = filled_len + MAX_FRAME_HEADER_LEN;
+        *write_buf.pos_mut() = filled_len + MAX_FRAME_HEADER_LEN;
+    }
+}
+
+/// Gives back the headroom left by `reserve_frame_header` if nothing was written after it.
+pub(crate) fn release_frame_header<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    gaps: &mut VecDeque<Range<usize>>,
+) {
+    let pos = write_buf.pos();
+    if pos == write_buf.filled_len() && has_frame_header_room(write_buf, gaps) {
+        gaps.pop_back();
+        unsafe {
+            *write_buf.filled_len_mut() = pos - MAX_FRAME_HEADER_LEN;
+            *write_buf.pos_mut() = pos - MAX_FRAME_HEADER_LEN;
+        }
+    }
+}
+
+fn has_frame_header_room<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &Cursor<u8, WRITE_BUFFER_LEN>,
+    gaps: &VecDeque<Range<usize>>,
+) -> bool {
+    let pos = write_buf.pos();
+    gaps.back()
+        .is_some_and(|gap| gap.end == pos && gap.len() == MAX_FRAME_HEADER_LEN)
+}
+
+/// Frames the unframed bytes after `pos` by writing the header right-aligned into the headroom
+/// ahead of them and shrinking its gap. Falls back to `write_frame` without headroom.
+pub(crate) fn frame_in_place<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    header0: u8,
+    gaps: &mut VecDeque<Range<usize>>,
+) -> Result<(), ()> {
+    if !has_frame_header_room(write_buf, gaps) {
+        return write_frame(write_buf, header0, None);
+    }
+    let pos = write_buf.pos();
+    let filled_len = write_buf.filled_len();
+    let mut header = Cursor::<u8, MAX_FRAME_HEADER_LEN>::new();
+    push_frame_header(&mut header, header0, filled_len - pos, None)?;
+    let header_pos = pos - header.filled_len();
+    write_buf.as_array()[header_pos..pos].copy_from_slice(header.filled());
+    if let Some(gap) = gaps.back_mut() {
+        gap.end = header_pos;
+    }
+    unsafe { *write_buf.pos_mut() = filled_len };
+    Ok(())
+}
+
+/// Appends a complete frame for `payload`, writing the header directly ahead of it.
+pub(crate) fn push_frame<const WRITE_BUFFER_LEN: usize>(
+    write_buf: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    header0: u8,
+    payload: &[u8],
+    masking_key: Option<[u8; MASK_KEY_LEN]>,
+) -> Result<(), ()> {
+    let mut header = Cursor::<u8, MAX_FRAME_HEADER_LEN>::new();
+    push_frame_header(&mut header, header0, payload.len(), masking_key)?;
+    let payload_pos = write_buf.filled_len() + header.filled_len();
+    let frame_end = payload_pos + payload.len();
+    if frame_end > WRITE_BUFFER_LEN {
+        return Err(());
+    }
+    let buffer = write_buf.as_array();
+    buffer[payload_pos - header.filled_len()..payload_pos].copy_from_slice(header.filled());
+    buffer[payload_pos..frame_end].copy_from_slice(payload);
+    mask_payload(&mut buffer[payload_pos..frame_end], masking_key);
+    unsafe {
+        *write_buf.filled_len_mut() = frame_end;
+        *write_buf.pos_mut() = frame_end;
+    }
+    Ok(())
+}
+
+fn mask_payload(payload: &mut [u8], masking_key: Option<[u8; MASK_KEY_LEN]>) {
+    if let Some(masking_key) = masking_key {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= masking_key[i % MASK_KEY_LEN];
+        }
+    }
+}
+
+fn push_frame_header<const WRITE_BUFFER_LEN: usize>(
+    buffer: &mut Cursor<u8, WRITE_BUFFER_LEN>,
+    header0: u8,
+    payload_len: usize,
+    masking_key: Option<[u8; MASK_KEY_LEN]>,
+) -> Result<(), ()> {
+    buffer.push(header0).map_err(|_| ())?;
+    let mask = if masking_key.is_some() { MASK } else { 0 };
+    if payload_len > u16::MAX as usize {
+        buffer.push(mask | 127).map_err(|_| ())?;
+        buffer
+            .write_all(&(payload_len as u64).to_be_bytes())
+            .map_err(|_| ())?;
+    } else if payload_len >= 126 {
+        buffer.push(mask | 126).map_err(|_| ())?;
+        buffer
+            .write_all(&(payload_len as u16).to_be_bytes())
+            .map_err(|_| ())?;
+    } else {
+        buffer.push(mask | payload_len as u8).map_err(|_| ())?;
+    }
+    match masking_key {
+        Some(masking_key) => buffer.write_all(&masking_key).map_err(|_| ()),
+        None => Ok(()),
+    }
+}
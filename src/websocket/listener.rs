@@ -0,0 +1,266 @@
+use std::{hash::Hash, io::Write, net::SocketAddr};
+
+use derive_more::{Deref, DerefMut};
+use qcell::{LCell, LCellOwner};
+
+use crate::{
+    pool::BufferStrategy,
+    socket::{ServerSocketListener, Socket, Sockets},
+};
+
+use super::{
+    flush_frames, websocket_message, websocket_read, websocket_reserve_header, HandshakeRequest,
+    HandshakeResponse, Message, ReadError, WebSocket, WebSocketConfig, WebSocketState,
+};
+
+#[derive(Deref, DerefMut)]
+#[repr(transparent)]
+pub struct WebSocketListener<T>(pub T);
+
+#[derive(Default, Deref, DerefMut)]
+pub struct WebSocketConnection<'id, T> {
+    pub(crate) websocket: LCell<'id, WebSocket>,
+    pub(crate) opened: bool,
+    #[deref]
+    #[deref_mut]
+    pub(crate) connection: T,
+}
+
+pub trait WebSocketHandler<'id>: Sized {
+    const MAX_CONNECTIONS: usize;
+    const READ_BUFFFER_LEN: usize;
+    const WRITE_BUFFER_LEN: usize;
+    const BUFFER_STRATEGY: BufferStrategy = BufferStrategy::Dedicated;
+    const WRITE_HIGH_WATER_MARK: usize = 0;
+    const CONFIG: WebSocketConfig = WebSocketConfig::DEFAULT;
+    type Connection: Default;
+    type RoomId: Eq + Hash + Clone = ();
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, WebSocketListener<Self>>,
+    ) where
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    fn handshake<'a>(_request: &HandshakeRequest<'a>) -> HandshakeResponse<'a> {
+        HandshakeResponse::Accept {
+            protocol: None,
+            headers: &[],
+        }
+    }
+
+    fn on_open(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) where
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    fn on_message(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+        message: Message,
+    ) where
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+
+    /// Called when `send` hit `WRITE_HIGH_WATER_MARK`. Closes the connection by default.
+    fn on_write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) where
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:,
+    {
+        connection.register_close_event(owner);
+    }
+
+    fn on_close(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) where
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+        [(); <WebSocketListener<Self> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:;
+}
+
+impl<'id, T: WebSocketHandler<'id>> WebSocketListener<T> {
+    fn inner<'a>(server: &'a LCell<'id, Self>) -> &'a LCell<'id, T> {
+        unsafe { &*(server as *const LCell<'id, Self> as *const LCell<'id, T>) }
+    }
+}
+
+impl<'id, T: WebSocketHandler<'id>> ServerSocketListener<'id> for WebSocketListener<T> {
+    const MAX_CONNECTIONS: usize = T::MAX_CONNECTIONS;
+    const READ_BUFFFER_LEN: usize = T::READ_BUFFFER_LEN;
+    const WRITE_BUFFER_LEN: usize = T::WRITE_BUFFER_LEN;
+    const BUFFER_STRATEGY: BufferStrategy = T::BUFFER_STRATEGY;
+    const WRITE_HIGH_WATER_MARK: usize = T::WRITE_HIGH_WATER_MARK;
+    type Connection = WebSocketConnection<'id, T::Connection>;
+    type RoomId = T::RoomId;
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::tick(Self::inner(server), owner, sockets)
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: SocketAddr,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        loop {
+            match websocket_read(
+                owner,
+                &connection.websocket,
+                &connection.read_buf,
+                &connection.write_buf,
+                &T::CONFIG,
+                T::handshake,
+            ) {
+                Ok(message) => {
+                    T::on_message(owner, Self::inner(server), connection, message);
+                    let read_buf = connection.read_buf.rw(owner);
+                    unsafe { *read_buf.pos_mut() += message.len };
+                    if read_buf.remaining() == 0 {
+                        read_buf.clear();
+                        break;
+                    }
+                }
+                Err(ReadError::NotFullRead) => break,
+                Err(ReadError::FlushRequest) => {
+                    connection.register_flush_event(owner);
+                    if connection.websocket.ro(owner).state != WebSocketState::Accepted {
+                        break;
+                    }
+                }
+                Err(ReadError::CloseRequest) => {
+                    connection.register_close_event(owner);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn flush(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        let opening = connection.websocket.ro(owner).state == WebSocketState::HandShaked;
+        if connection.flush_frames(owner).is_err() {
+            return connection.register_close_event(owner);
+        }
+        if opening {
+            connection.opened = true;
+            connection.reserve_frame_header(owner);
+            T::on_open(owner, Self::inner(server), connection);
+            if connection.flush_frames(owner).is_err() {
+                return connection.register_close_event(owner);
+            }
+        }
+        connection.reserve_frame_header(owner);
+    }
+
+    fn write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        T::on_write_queue_full(owner, Self::inner(server), connection)
+    }
+
+    fn close(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) where
+        [(); Self::READ_BUFFFER_LEN]:,
+        [(); Self::WRITE_BUFFER_LEN]:,
+        [(); Self::MAX_CONNECTIONS]:,
+    {
+        if connection.opened {
+            T::on_close(owner, Self::inner(server), connection);
+        }
+    }
+}
+
+impl<'id, T: WebSocketHandler<'id>> Socket<'id, '_, WebSocketListener<T>>
+where
+    [(); <WebSocketListener<T> as ServerSocketListener<'id>>::READ_BUFFFER_LEN]:,
+    [(); <WebSocketListener<T> as ServerSocketListener<'id>>::WRITE_BUFFER_LEN]:,
+    [(); <WebSocketListener<T> as ServerSocketListener<'id>>::MAX_CONNECTIONS]:,
+{
+    pub fn send(&mut self, owner: &mut LCellOwner<'id>, payload: &[u8]) -> Result<(), ()> {
+        let websocket = &self.connection.websocket;
+        let gaps = &mut self.write_gaps;
+        let Some(frame) = websocket_message(owner, websocket, &self.write_buf, gaps, payload)?
+        else {
+            self.write_buf
+                .rw(owner)
+                .write_all(payload)
+                .map_err(|_| ())?;
+            self.register_flush_event(owner);
+            return Ok(());
+        };
+        self.write_with(owner, &frame)?;
+        self.reserve_frame_header(owner);
+        Ok(())
+    }
+
+    pub fn payload<'a>(&'a self, owner: &'a LCellOwner<'id>, message: &Message) -> &'a [u8] {
+        let read_buf = self.read_buf.ro(owner);
+        &read_buf.filled()[read_buf.pos()..read_buf.pos() + message.len]
+    }
+
+    fn flush_frames(&mut self, owner: &mut LCellOwner<'id>) -> Result<(), ()> {
+        let websocket = &self.connection.websocket;
+        flush_frames(owner, websocket, &self.write_buf, &mut self.write_gaps)
+    }
+
+    fn reserve_frame_header(&mut self, owner: &mut LCellOwner<'id>) {
+        let websocket = &self.connection.websocket;
+        websocket_reserve_header(owner, websocket, &self.write_buf, &mut self.write_gaps)
+    }
+}
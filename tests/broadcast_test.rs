@@ -0,0 +1,146 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ConnectionId, ServerSocketListener, Socket, Sockets};
+
+#[test]
+fn test_broadcast_reaches_matching_sockets() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                ChatServer::default(),
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut streams: Vec<TcpStream> = (0..3)
+        .map(|_| {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(500)))
+                .unwrap();
+            stream
+        })
+        .collect();
+    streams[0].write_all(b"!").unwrap();
+
+    for stream in &mut streams[1..] {
+        let mut message = [0; 5];
+        stream.read_exact(&mut message).unwrap();
+        assert_eq!(&message, b"hello");
+    }
+    assert_silent(&mut streams[0]);
+
+    streams[1].write_all(b"?").unwrap();
+    for i in [0, 2] {
+        let stream = &mut streams[i];
+        let mut message = [0; 5];
+        stream.read_exact(&mut message).unwrap();
+        assert_eq!(&message, b"tick!");
+    }
+    assert_silent(&mut streams[1]);
+}
+
+#[test]
+fn test_broadcast_keeps_order_with_queued_sends() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                ChatServer::default(),
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"=").unwrap();
+    let mut messages = [0; 3];
+    stream.read_exact(&mut messages).unwrap();
+    assert_eq!(&messages, b"123");
+}
+
+fn assert_silent(stream: &mut TcpStream) {
+    let err = stream.read(&mut [0; 5]).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+}
+
+#[derive(Default)]
+pub struct ChatServer {
+    announcer: Option<ConnectionId>,
+}
+
+impl<'id> ServerSocketListener<'id> for ChatServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = ();
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        if let Some(announcer) = server.rw(owner).announcer.take() {
+            sockets.broadcast(owner, |id, _| id != announcer, b"tick!");
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let sender = connection.id();
+        if connection.read_buf.ro(owner).filled() == b"?" {
+            server.rw(owner).announcer = Some(sender);
+        } else if connection.read_buf.ro(owner).filled() == b"=" {
+            let handle = connection.handle();
+            handle.send(owner, sender, b"1");
+            handle.broadcast(owner, |_, _| true, b"2");
+            handle.send(owner, sender, b"3");
+        } else {
+            let handle = connection.handle();
+            handle.broadcast(owner, move |id, _| id != sender, b"hello");
+        }
+        connection.read_buf.rw(owner).clear();
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,101 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Mutex,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::{
+    pool::BufferStrategy,
+    socket::{ServerSocketListener, Socket, Sockets},
+};
+
+static CHUNKS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_drained_buffers_are_shared() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                PooledServer,
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut streams = Vec::new();
+    for payload in [b"first", b"other"] {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(payload).unwrap();
+        let mut echo = [0; 5];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, payload);
+        streams.push(stream);
+    }
+
+    let chunks = CHUNKS.lock().unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0], chunks[1]);
+}
+
+pub struct PooledServer;
+
+impl<'id> ServerSocketListener<'id> for PooledServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    const BUFFER_STRATEGY: BufferStrategy = BufferStrategy::Pooled;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        CHUNKS
+            .lock()
+            .unwrap()
+            .push(&*connection.read_buf as *const _ as usize);
+        let (read_buf, write_buf) = owner.rw2(&connection.read_buf, &connection.write_buf);
+        write_buf.write_all(read_buf.filled()).unwrap();
+        read_buf.clear();
+        connection.register_flush_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,304 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::{
+    codec::{MessageHandler, MessageListener},
+    framing::{LengthDelimited, LengthPrefix},
+    socket::{ServerSocketListener, Socket, Sockets},
+};
+
+static ECHOED: AtomicBool = AtomicBool::new(false);
+static BURST_RECEIVED: AtomicBool = AtomicBool::new(false);
+static QUEUE_FULL: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_message_listener_echo() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            MessageListener(EchoServer),
+            RawClient,
+            Duration::from_millis(50),
+        )
+    });
+    assert!(ECHOED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_send_spills_into_overflow_in_order() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            MessageListener(BurstServer),
+            BurstClient,
+            Duration::from_millis(50),
+        )
+    });
+    assert!(BURST_RECEIVED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_send_past_high_water_mark_calls_handler() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            MessageListener(FloodServer),
+            BurstClient,
+            Duration::from_millis(50),
+        )
+    });
+    assert!(QUEUE_FULL.load(Ordering::Relaxed));
+}
+
+pub struct EchoServer;
+
+impl<'id> MessageHandler<'id> for EchoServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 100;
+    type Connection = ();
+    type Codec = LengthDelimited;
+    const CODEC: Self::Codec = LengthDelimited::new(LengthPrefix::U16Be, 64);
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, MessageListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+    }
+
+    fn on_message(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+        message: Vec<u8>,
+    ) {
+        connection.send(owner, &message).unwrap();
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+    }
+}
+
+pub struct RawClient;
+
+const FRAMES: &[u8] = b"\x00\x05hello\x00\x05world";
+
+impl<'id> ServerSocketListener<'id> for RawClient {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 100;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        connection.write_buf.rw(owner).write_all(FRAMES).unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        if connection.read_buf.ro(owner).filled() == FRAMES {
+            ECHOED.store(true, Ordering::Relaxed);
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+fn burst() -> Vec<u8> {
+    (0..10u8).flat_map(|i| [0, 5, i, i, i, i, i]).collect()
+}
+
+pub struct BurstServer;
+
+impl<'id> MessageHandler<'id> for BurstServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 16;
+    const WRITE_HIGH_WATER_MARK: usize = 256;
+    type Connection = ();
+    type Codec = LengthDelimited;
+    const CODEC: Self::Codec = LengthDelimited::new(LengthPrefix::U16Be, 8);
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, MessageListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+        for i in 0..10 {
+            connection.send(owner, &vec![i; 5]).unwrap();
+        }
+        assert_ne!(connection.overflow_len(), 0);
+    }
+
+    fn on_message(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+        _message: Vec<u8>,
+    ) {
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+    }
+}
+
+pub struct FloodServer;
+
+impl<'id> MessageHandler<'id> for FloodServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 16;
+    const WRITE_HIGH_WATER_MARK: usize = 16;
+    type Connection = ();
+    type Codec = LengthDelimited;
+    const CODEC: Self::Codec = LengthDelimited::new(LengthPrefix::U16Be, 8);
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, MessageListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+        let sent = (0..10).take_while(|&i| connection.send(owner, &vec![i; 5]).is_ok());
+        assert_eq!(sent.count(), 4);
+    }
+
+    fn on_message(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+        _message: Vec<u8>,
+    ) {
+    }
+
+    fn on_write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+        QUEUE_FULL.store(true, Ordering::Relaxed);
+        connection.register_close_event(owner);
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, MessageListener<Self>>,
+    ) {
+    }
+}
+
+pub struct BurstClient;
+
+impl<'id> ServerSocketListener<'id> for BurstClient {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 100;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let received = connection.read_buf.ro(owner).filled();
+        assert!(burst().starts_with(received));
+        if received.len() == burst().len() {
+            BURST_RECEIVED.store(true, Ordering::Relaxed);
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,16 @@
+use std::{
+    net::{SocketAddr, TcpListener},
+    thread,
+};
+
+/// Binds an ephemeral local port and runs `serve` on it in a new thread. The port stays bound
+/// throughout, so connections made as soon as this returns wait in the listener's backlog.
+pub fn spawn_server<F>(serve: F) -> SocketAddr
+where
+    F: FnOnce(TcpListener) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || serve(listener));
+    addr
+}
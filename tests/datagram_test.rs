@@ -0,0 +1,114 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+use std::{
+    net::{SocketAddr, TcpListener, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::{
+    datagram::{DatagramListener, DatagramSocket},
+    mio::listen_on_with_datagram,
+    socket::{ServerSocketListener, Socket, Sockets},
+};
+
+#[test]
+fn test_datagram_echo() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let udp_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let datagram_addr = udp_socket.local_addr().unwrap();
+    thread::spawn(move || {
+        LCellOwner::scope(|mut owner| {
+            listen_on_with_datagram(
+                &mut owner,
+                ControlServer,
+                listener,
+                EchoServer,
+                udp_socket,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = [0u8; 64];
+    client.send_to(b"position", datagram_addr).unwrap();
+    let (len, from) = client.recv_from(&mut buf).unwrap();
+    assert_eq!(from, datagram_addr);
+    assert_eq!(&buf[..len], b"position");
+}
+
+pub struct EchoServer;
+
+impl<'id> DatagramListener<'id> for EchoServer {
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    const MAX_QUEUED_DATAGRAMS: usize = 8;
+
+    fn tick(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _socket: &mut DatagramSocket<'id, Self>,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        socket: &mut DatagramSocket<'id, Self>,
+        addr: SocketAddr,
+        datagram: &[u8],
+    ) {
+        let _result = socket.send_to(owner, addr, datagram);
+    }
+}
+
+pub struct ControlServer;
+
+impl<'id> ServerSocketListener<'id> for ControlServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 100;
+    const WRITE_BUFFER_LEN: usize = 100;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: SocketAddr,
+    ) {
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use fast_collections::Cursor;
+use socket_server::framing::{LengthDelimited, LengthPrefix, LineDelimited};
+
+#[test]
+fn test_length_prefix_round_trip() {
+    for prefix in [
+        LengthPrefix::U16Be,
+        LengthPrefix::U16Le,
+        LengthPrefix::U32Be,
+        LengthPrefix::U32Le,
+        LengthPrefix::Varint,
+    ] {
+        let codec = LengthDelimited::new(prefix, 300);
+        let mut buf = Cursor::<u8, 1024>::new();
+        codec.encode(&mut buf, b"hello").unwrap();
+        codec.encode(&mut buf, &[7; 300]).unwrap();
+        assert!(codec.encode(&mut buf, &[7; 301]).is_err());
+
+        let frame = codec.next_frame(&mut buf).unwrap().unwrap();
+        assert_eq!(&buf.filled()[frame], b"hello");
+        let frame = codec.next_frame(&mut buf).unwrap().unwrap();
+        assert_eq!(&buf.filled()[frame], &[7; 300]);
+        assert_eq!(codec.next_frame(&mut buf).unwrap(), None);
+        assert_eq!(buf.filled_len(), 0);
+    }
+}
+
+#[test]
+fn test_partial_frame_is_compacted() {
+    let codec = LengthDelimited::new(LengthPrefix::Varint, 1024);
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(b"\x02hi\x05wor").unwrap();
+    let frame = codec.next_frame(&mut buf).unwrap().unwrap();
+    assert_eq!(&buf.filled()[frame], b"hi");
+    assert_eq!(codec.next_frame(&mut buf).unwrap(), None);
+    assert_eq!(buf.pos(), 0);
+    assert_eq!(buf.filled(), b"\x05wor");
+
+    buf.write_all(b"ld").unwrap();
+    let frame = codec.next_frame(&mut buf).unwrap().unwrap();
+    assert_eq!(&buf.filled()[frame], b"world");
+}
+
+#[test]
+fn test_oversized_frame_is_rejected() {
+    let codec = LengthDelimited::new(LengthPrefix::U16Be, 16);
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(&[0, 17]).unwrap();
+    assert!(codec.next_frame(&mut buf).is_err());
+
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(&[0xff; 11]).unwrap();
+    assert!(LengthPrefix::Varint.decode(buf.filled()).is_err());
+}
+
+#[test]
+fn test_line_delimited() {
+    let codec = LineDelimited::new(8);
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(b"kick bob\r\nlist\nsta").unwrap();
+    let line = codec.next_line(&mut buf).unwrap().unwrap();
+    assert_eq!(&buf.filled()[line], b"kick bob");
+    let line = codec.next_line(&mut buf).unwrap().unwrap();
+    assert_eq!(&buf.filled()[line], b"list");
+    assert_eq!(codec.next_line(&mut buf).unwrap(), None);
+    assert_eq!(buf.filled(), b"sta");
+
+    buf.write_all(b"tus\r\n").unwrap();
+    let line = codec.next_line(&mut buf).unwrap().unwrap();
+    assert_eq!(&buf.filled()[line], b"status");
+
+    let mut encoded = Cursor::<u8, 64>::new();
+    codec.encode(&mut encoded, "ok").unwrap();
+    assert_eq!(encoded.filled(), b"ok\r\n");
+}
+
+#[test]
+fn test_line_delimited_rejects_invalid_lines() {
+    let codec = LineDelimited::new(8);
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(b"too long line").unwrap();
+    assert!(codec.next_line(&mut buf).is_err());
+
+    let mut buf = Cursor::<u8, 64>::new();
+    buf.write_all(b"\xff\xfe\n").unwrap();
+    assert!(codec.next_line(&mut buf).is_err());
+}
@@ -0,0 +1,99 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ServerSocketListener, Socket, Sockets};
+
+#[test]
+fn test_metrics_endpoint() {
+    let metrics_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let metrics_addr = metrics_listener.local_addr().unwrap();
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on_with_metrics(
+                &mut owner,
+                EchoServer,
+                listener,
+                Duration::from_millis(50),
+                metrics_listener,
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"hello").unwrap();
+    let mut echo = [0; 5];
+    stream.read_exact(&mut echo).unwrap();
+
+    let mut scrape = TcpStream::connect(metrics_addr).unwrap();
+    scrape
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    scrape
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    scrape.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\nsocket_server_accepted_total 1\n"));
+    assert!(response.contains("\nsocket_server_read_bytes_total 5\n"));
+    assert!(response.contains("\nsocket_server_written_bytes_total 5\n"));
+    assert!(response.contains("\nsocket_server_open_connections 1\n"));
+}
+
+pub struct EchoServer;
+
+impl<'id> ServerSocketListener<'id> for EchoServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let (read_buf, write_buf) = owner.rw2(&connection.read_buf, &connection.write_buf);
+        write_buf.write_all(read_buf.filled()).unwrap();
+        read_buf.clear();
+        connection.register_flush_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
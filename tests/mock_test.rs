@@ -5,7 +5,7 @@ use std::time::Duration;
 
 use qcell::{LCell, LCellOwner};
 #[cfg(test)]
-use socket_server::socket::{ServerSocketListener, Socket};
+use socket_server::socket::{ServerSocketListener, Socket, Sockets};
 
 #[test]
 fn test_mocking_system() {
@@ -30,7 +30,12 @@ impl<'id> ServerSocketListener<'id> for ApplicationServer {
     const WRITE_BUFFER_LEN: usize = 100;
     type Connection = Player;
 
-    fn tick(_server: &LCell<'id, Self>, _owner: &mut LCellOwner<'id>) {}
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
 
     fn accept(
         owner: &mut LCellOwner<'id>,
@@ -73,7 +78,12 @@ impl<'id> ServerSocketListener<'id> for MockServer {
     const WRITE_BUFFER_LEN: usize = 100;
     type Connection = MockPlayer;
 
-    fn tick(_server: &LCell<'id, Self>, _owner: &mut LCellOwner<'id>) {}
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
 
     fn accept(
         _owner: &mut LCellOwner<'id>,
@@ -0,0 +1,341 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ServerSocketListener, Socket, Sockets};
+
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static FULL: AtomicBool = AtomicBool::new(false);
+
+const BURST_LEN: usize = 1 << 20;
+const SLOW_LEN: usize = 16 << 10;
+
+#[test]
+fn test_partially_consumed_read_buffer_is_compacted() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(&mut owner, SlowServer, Sender, Duration::from_millis(50))
+    });
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), 60);
+}
+
+#[test]
+fn test_full_read_buffer_is_not_eof() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(&mut owner, StalledServer, Sender, Duration::from_millis(50))
+    });
+    assert!(FULL.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_burst_beyond_read_buffer_is_read_in_full() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                CountingServer,
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&vec![1; BURST_LEN]).unwrap();
+    let mut done = [0; 4];
+    stream.read_exact(&mut done).unwrap();
+    assert_eq!(&done, b"done");
+}
+
+#[test]
+fn test_slow_consumer_keeps_reading_after_compaction() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                TickConsumer,
+                listener,
+                Duration::from_millis(1),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&vec![1; SLOW_LEN]).unwrap();
+    let mut done = [0; 4];
+    stream.read_exact(&mut done).unwrap();
+    assert_eq!(&done, b"done");
+}
+
+pub struct SlowServer;
+
+impl<'id> ServerSocketListener<'id> for SlowServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 16;
+    const WRITE_BUFFER_LEN: usize = 128;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let read_buf = connection.read_buf.rw(owner);
+        let consumed = read_buf.remaining() - read_buf.remaining() % 5;
+        unsafe { *read_buf.pos_mut() += consumed };
+        if RECEIVED.fetch_add(consumed, Ordering::Relaxed) + consumed == 60 {
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct Sender;
+
+impl<'id> ServerSocketListener<'id> for Sender {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 128;
+    const WRITE_BUFFER_LEN: usize = 128;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        connection.write_buf.rw(owner).write_all(&[1; 64]).unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct StalledServer;
+
+impl<'id> ServerSocketListener<'id> for StalledServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 16;
+    const WRITE_BUFFER_LEN: usize = 128;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn read_buffer_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        assert_eq!(connection.read_buf.ro(owner).filled_len(), 16);
+        FULL.store(true, Ordering::Relaxed);
+        connection.register_close_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+#[derive(Default)]
+pub struct Counted(usize);
+
+pub struct CountingServer;
+
+impl<'id> ServerSocketListener<'id> for CountingServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = Counted;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let read_buf = connection.read_buf.rw(owner);
+        let read_len = read_buf.remaining();
+        read_buf.clear();
+        connection.0 += read_len;
+        if connection.0 == BURST_LEN {
+            connection.write(owner, b"done").unwrap();
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+/// Consumes at most `CONSUMED_PER_TICK` bytes per tick and none on read, so `read_buf` fills up.
+pub struct TickConsumer;
+
+const CONSUMED_PER_TICK: usize = 128;
+
+impl<'id> ServerSocketListener<'id> for TickConsumer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 256;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = Counted;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        for socket in sockets.iter_mut() {
+            let read_buf = socket.read_buf.rw(owner);
+            let consumed = read_buf.remaining().min(CONSUMED_PER_TICK);
+            unsafe { *read_buf.pos_mut() += consumed };
+            socket.0 += consumed;
+            if consumed != 0 && socket.0 == SLOW_LEN {
+                socket.write(owner, b"done").unwrap();
+            }
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,108 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ServerSocketListener, Socket, Sockets};
+
+static READS: AtomicUsize = AtomicUsize::new(0);
+static RESUME: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_paused_socket_is_not_read() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                PausingServer,
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(b"first").unwrap();
+    let mut echo = [0; 5];
+    stream.read_exact(&mut echo).unwrap();
+    assert_eq!(&echo, b"first");
+
+    stream.write_all(b"second").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(READS.load(Ordering::Relaxed), 1);
+
+    RESUME.store(true, Ordering::Relaxed);
+    let mut echo = [0; 6];
+    stream.read_exact(&mut echo).unwrap();
+    assert_eq!(&echo, b"second");
+    assert_eq!(READS.load(Ordering::Relaxed), 2);
+}
+
+pub struct PausingServer;
+
+impl<'id> ServerSocketListener<'id> for PausingServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        if RESUME.swap(false, Ordering::Relaxed) {
+            for socket in sockets.iter_mut() {
+                socket.resume_reading(owner);
+            }
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        READS.fetch_add(1, Ordering::Relaxed);
+        let (read_buf, write_buf) = owner.rw2(&connection.read_buf, &connection.write_buf);
+        write_buf.write_all(read_buf.filled()).unwrap();
+        read_buf.clear();
+        connection.pause_reading(owner);
+        assert!(connection.is_reading_paused());
+        connection.register_flush_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
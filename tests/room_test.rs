@@ -0,0 +1,139 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ConnectionId, ServerSocketListener, Socket, Sockets};
+
+static MEMBERS: AtomicUsize = AtomicUsize::new(0);
+static STALE_REJECTED: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_broadcast_to_room() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                RoomServer::default(),
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut clients = Vec::new();
+    for room in [1, 1, 2] {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        stream.write_all(&[room]).unwrap();
+        let mut joined = [0];
+        stream.read_exact(&mut joined).unwrap();
+        assert_eq!(joined, [room]);
+        clients.push(stream);
+    }
+
+    clients[0].write_all(b"hi").unwrap();
+    let mut received = [0; 2];
+    for client in &mut clients[..2] {
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hi");
+    }
+    let err = clients[2].read(&mut received).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+    assert_eq!(MEMBERS.load(Ordering::Relaxed), 2);
+
+    drop(clients.remove(1));
+    thread::sleep(Duration::from_millis(200));
+    clients[0].write_all(b"yo").unwrap();
+    clients[0].read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"yo");
+    assert_eq!(MEMBERS.load(Ordering::Relaxed), 1);
+    assert!(STALE_REJECTED.load(Ordering::Relaxed));
+}
+
+#[derive(Default)]
+pub struct RoomServer {
+    closed: Option<ConnectionId>,
+}
+
+impl<'id> ServerSocketListener<'id> for RoomServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = Option<u8>;
+    type RoomId = u8;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let handle = connection.handle();
+        let payload = connection.read_buf.ro(owner).filled().to_vec();
+        connection.read_buf.rw(owner).clear();
+        match **connection {
+            None => {
+                **connection = Some(payload[0]);
+                assert!(handle.join(owner, payload[0], connection.id()));
+                connection.write(owner, &payload[..1]).unwrap();
+            }
+            Some(room) => {
+                if let Some(closed) = server.ro(owner).closed {
+                    let rejected = !handle.join(owner, room, closed)
+                        && handle.rooms(owner).rooms(closed).next().is_none();
+                    STALE_REJECTED.store(rejected, Ordering::Relaxed);
+                }
+                MEMBERS.store(
+                    handle.rooms(owner).members(&room).count(),
+                    Ordering::Relaxed,
+                );
+                handle.broadcast_to_room(owner, room, &payload);
+            }
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        assert!(!connection.handle().join(owner, 0, connection.id()));
+        server.rw(owner).closed = Some(connection.id());
+    }
+}
@@ -0,0 +1,99 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ConnectionId, ServerSocketListener, Socket, Sockets};
+
+#[test]
+fn test_send_and_close_by_id() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                RelayServer::default(),
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut first = TcpStream::connect(addr).unwrap();
+    first
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut second = TcpStream::connect(addr).unwrap();
+    second
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    second.write_all(b"hello").unwrap();
+    let mut relayed = [0; 5];
+    first.read_exact(&mut relayed).unwrap();
+    assert_eq!(&relayed, b"hello");
+    let mut rest = Vec::new();
+    second.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[derive(Default)]
+pub struct RelayServer {
+    first: Option<ConnectionId>,
+}
+
+impl<'id> ServerSocketListener<'id> for RelayServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        server.rw(owner).first.get_or_insert(connection.id());
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let first = server.ro(owner).first.unwrap();
+        let handle = connection.handle();
+        let payload = connection.read_buf.ro(owner).filled().to_vec();
+        connection.read_buf.rw(owner).clear();
+        handle.send(owner, first, &payload);
+        handle.close(owner, connection.id());
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
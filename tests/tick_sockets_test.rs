@@ -0,0 +1,132 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ConnectionId, ServerSocketListener, Socket, Sockets};
+
+static ACCEPTED: Mutex<Vec<ConnectionId>> = Mutex::new(Vec::new());
+static STALE_FOUND: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_tick_writes_and_closes_sockets() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                TickingServer::default(),
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut greeting = [0; 4];
+    stream.read_exact(&mut greeting).unwrap();
+    assert_eq!(&greeting, b"tick");
+
+    stream.write_all(b"kick").unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.read_exact(&mut greeting).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(!STALE_FOUND.load(Ordering::Relaxed));
+
+    let accepted = ACCEPTED.lock().unwrap();
+    assert_eq!(accepted[0].index(), accepted[1].index());
+    assert_ne!(accepted[0], accepted[1]);
+}
+
+#[derive(Default)]
+pub struct TickingServer {
+    kicked: Vec<ConnectionId>,
+    closed: Vec<ConnectionId>,
+}
+
+#[derive(Default)]
+pub struct Player {
+    greeted: bool,
+}
+
+impl<'id> ServerSocketListener<'id> for TickingServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64;
+    type Connection = Player;
+
+    fn tick(
+        server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        for socket in sockets.iter_mut() {
+            if !socket.greeted {
+                socket.greeted = true;
+                socket.write(owner, b"tick").unwrap();
+            }
+        }
+        for &id in server.ro(owner).closed.iter() {
+            if sockets.get_mut(id).is_some() {
+                STALE_FOUND.store(true, Ordering::Relaxed);
+            }
+        }
+        for id in std::mem::take(&mut server.rw(owner).kicked) {
+            sockets.close(owner, id).unwrap();
+            server.rw(owner).closed.push(id);
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        ACCEPTED.lock().unwrap().push(connection.id());
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        connection.read_buf.rw(owner).clear();
+        server.rw(owner).kicked.push(connection.id());
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,211 @@
+#![cfg(feature = "tls")]
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use rustls::{
+    pki_types::{CertificateDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, StreamOwned,
+};
+use socket_server::{
+    socket::{ServerSocketListener, Socket, Sockets},
+    tls::{listen_tls_on, load_certified_key, load_server_config, CertificateSet, SniResolver},
+};
+
+#[test]
+fn test_tls_echo() {
+    let dir = temp_dir("echo");
+    let (cert, cert_path, key_path) = self_signed(&dir, "localhost");
+    let addr = spawn_server(load_server_config(&cert_path, &key_path).unwrap());
+
+    let mut stream = connect(addr, "localhost", cert);
+    assert_echo(&mut stream, b"hello");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_sni_resolver_reload() {
+    let dir = temp_dir("sni");
+    let (alpha, alpha_cert, alpha_key) = self_signed(&dir, "alpha.test");
+    let (beta, beta_cert, beta_key) = self_signed(&dir, "beta.test");
+    let mut certificates = CertificateSet::new();
+    certificates.insert(
+        "alpha.test",
+        load_certified_key(&alpha_cert, &alpha_key).unwrap(),
+    );
+    certificates.insert(
+        "beta.test",
+        load_certified_key(&beta_cert, &beta_key).unwrap(),
+    );
+    let resolver = SniResolver::new(certificates);
+    let addr = spawn_server(resolver.server_config().unwrap());
+
+    let mut alpha_stream = connect(addr, "alpha.test", alpha.clone());
+    assert_echo(&mut alpha_stream, b"alpha");
+    assert_echo(&mut connect(addr, "beta.test", beta), b"beta");
+
+    let (renewed, renewed_cert, renewed_key) = self_signed(&dir, "alpha.test");
+    let mut certificates = CertificateSet::new();
+    certificates.insert(
+        "alpha.test",
+        load_certified_key(&renewed_cert, &renewed_key).unwrap(),
+    );
+    resolver.replace(certificates);
+
+    assert_echo(&mut connect(addr, "alpha.test", renewed), b"renewed");
+    assert_echo(&mut alpha_stream, b"still alive");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_tls_slow_reader_receives_everything() {
+    let dir = temp_dir("slow");
+    let (cert, cert_path, key_path) = self_signed(&dir, "localhost");
+    let addr = spawn_server(load_server_config(&cert_path, &key_path).unwrap());
+
+    let mut stream = connect(addr, "localhost", cert);
+    stream.write_all(b"bulk").unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let mut received = vec![0u8; BULK_LEN];
+    for chunk in received.chunks_mut(64 * 1024) {
+        stream.read_exact(chunk).unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(received
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| byte == i as u8));
+    assert_echo(&mut stream, b"still alive");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("socket_server_tls_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn self_signed(dir: &Path, name: &str) -> (CertificateDer<'static>, PathBuf, PathBuf) {
+    let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    let id = rand::random::<u32>();
+    let cert_path = dir.join(format!("{name}.{id}.cert.pem"));
+    let key_path = dir.join(format!("{name}.{id}.key.pem"));
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+    (certified.cert.der().clone(), cert_path, key_path)
+}
+
+fn spawn_server(config: Arc<ServerConfig>) -> SocketAddr {
+    common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            listen_tls_on(
+                &mut owner,
+                EchoServer,
+                listener,
+                Duration::from_millis(50),
+                config,
+            )
+        })
+    })
+}
+
+fn connect(
+    addr: SocketAddr,
+    name: &str,
+    root: CertificateDer<'static>,
+) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots.add(root).unwrap();
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connection = ClientConnection::new(
+        Arc::new(config),
+        ServerName::try_from(name.to_string()).unwrap(),
+    )
+    .unwrap();
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    StreamOwned::new(connection, stream)
+}
+
+fn assert_echo(stream: &mut StreamOwned<ClientConnection, TcpStream>, message: &[u8]) {
+    stream.write_all(message).unwrap();
+    let mut echoed = vec![0u8; message.len()];
+    stream.read_exact(&mut echoed).unwrap();
+    assert_eq!(echoed, message);
+}
+
+const BULK_LEN: usize = 16 * 1024 * 1024;
+
+pub struct EchoServer;
+
+impl<'id> ServerSocketListener<'id> for EchoServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 4096;
+    const WRITE_HIGH_WATER_MARK: usize = 2 * BULK_LEN;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        if connection.read_buf.ro(owner).filled() == b"bulk" {
+            connection.read_buf.rw(owner).clear();
+            let data: Vec<u8> = (0..BULK_LEN).map(|i| i as u8).collect();
+            connection.write(owner, &data).unwrap();
+            return;
+        }
+        let (read_buf, write_buf) = owner.rw2(&connection.read_buf, &connection.write_buf);
+        write_buf.write_all(read_buf.filled()).unwrap();
+        read_buf.clear();
+        connection.register_flush_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,194 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+use std::{
+    io::Write,
+    net::{SocketAddr, UdpSocket},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::{
+    mio::listen_udp_on,
+    socket::{ServerSocketListener, Socket, Sockets},
+};
+
+static ACCEPTED: AtomicUsize = AtomicUsize::new(0);
+static CLOSED: AtomicUsize = AtomicUsize::new(0);
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static RESUME: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_udp_connection_lifecycle() {
+    let udp_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = udp_socket.local_addr().unwrap();
+    thread::spawn(move || {
+        LCellOwner::scope(|mut owner| {
+            listen_udp_on(
+                &mut owner,
+                EchoServer,
+                udp_socket,
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+            )
+        })
+    });
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut buf = [0u8; 64];
+    let mut echo = |message: &[u8]| {
+        client.send_to(message, addr).unwrap();
+        client
+            .recv_from(&mut buf)
+            .map(|(len, _addr)| buf[..len].to_vec())
+    };
+    assert_eq!(echo(b"hello").unwrap(), b"hello");
+    assert_eq!(echo(b"again").unwrap(), b"again");
+    assert_eq!(ACCEPTED.load(Ordering::Relaxed), 1);
+
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(CLOSED.load(Ordering::Relaxed), 1);
+    assert_eq!(echo(b"back").unwrap(), b"back");
+    assert_eq!(ACCEPTED.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_unread_datagrams_are_bounded_and_resumed() {
+    let udp_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = udp_socket.local_addr().unwrap();
+    thread::spawn(move || {
+        LCellOwner::scope(|mut owner| {
+            listen_udp_on(
+                &mut owner,
+                PausingServer,
+                udp_socket,
+                Duration::from_millis(10),
+                Duration::from_secs(10),
+            )
+        })
+    });
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.send_to(&[0; 300], addr).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), 300);
+
+    client.send_to(&[1; 300], addr).unwrap();
+    client.send_to(&[2; 300], addr).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), 300);
+
+    RESUME.store(true, Ordering::Relaxed);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), 600);
+}
+
+pub struct EchoServer;
+
+impl<'id> ServerSocketListener<'id> for EchoServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: SocketAddr,
+    ) {
+        ACCEPTED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let (read_buf, write_buf) = owner.rw2(&connection.read_buf, &connection.write_buf);
+        write_buf.write_all(read_buf.filled()).unwrap();
+        read_buf.clear();
+        connection.register_flush_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+        CLOSED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct PausingServer;
+
+impl<'id> ServerSocketListener<'id> for PausingServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        if RESUME.load(Ordering::Relaxed) {
+            for socket in sockets.iter_mut() {
+                socket.resume_reading(owner);
+            }
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let read_buf = connection.read_buf.rw(owner);
+        RECEIVED.fetch_add(read_buf.filled_len(), Ordering::Relaxed);
+        read_buf.clear();
+        connection.pause_reading(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,432 @@
+#![cfg(feature = "websocket")]
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::{
+    socket::{ServerSocketListener, Socket, Sockets},
+    websocket::{
+        websocket_client_flush, websocket_client_read, websocket_connect, Message, ReadError,
+        WebSocket, WebSocketHandler, WebSocketListener, WebSocketState,
+    },
+};
+
+static ECHOED: AtomicBool = AtomicBool::new(false);
+static CLIENT_ECHOED: AtomicBool = AtomicBool::new(false);
+static BURST_RECEIVED: AtomicBool = AtomicBool::new(false);
+static RAW_FRAMED: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn test_websocket_listener_echo() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            WebSocketListener(EchoServer),
+            RawClient(b"\x82\x05hello"),
+            Duration::from_millis(50),
+        )
+    });
+    assert!(ECHOED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_bytes_written_straight_into_write_buf_are_framed_in_place() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            WebSocketListener(RawWriteServer),
+            RawClient(b"\x82\x03raw\x82\x04sent\x82\x04tail"),
+            Duration::from_millis(50),
+        )
+    });
+    assert!(RAW_FRAMED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_websocket_client_echo() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            WebSocketListener(EchoServer),
+            Client,
+            Duration::from_millis(50),
+        )
+    });
+    assert!(CLIENT_ECHOED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_send_spills_into_overflow_in_order() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(
+            &mut owner,
+            WebSocketListener(BurstServer),
+            BurstClient,
+            Duration::from_millis(50),
+        )
+    });
+    assert!(BURST_RECEIVED.load(Ordering::Relaxed));
+}
+
+pub struct EchoServer;
+
+impl<'id> WebSocketHandler<'id> for EchoServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, WebSocketListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+    }
+
+    fn on_message(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+        message: Message,
+    ) {
+        let payload = connection.payload(owner, &message).to_vec();
+        connection.send(owner, &payload).unwrap();
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+    }
+}
+
+/// Writes the message's payload straight into `write_buf` around a `send`, leaving the first and
+/// last part for the flush to frame.
+pub struct RawWriteServer;
+
+impl<'id> WebSocketHandler<'id> for RawWriteServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, WebSocketListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+    }
+
+    fn on_message(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+        _message: Message,
+    ) {
+        connection.write_buf.rw(owner).write_all(b"raw").unwrap();
+        connection.send(owner, b"sent").unwrap();
+        connection.write_buf.rw(owner).write_all(b"tail").unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+    }
+}
+
+pub struct RawClient(&'static [u8]);
+#[derive(Default)]
+pub struct RawClientConnection {
+    upgraded: bool,
+}
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\n\
+Host: example.com\r\n\
+Upgrade: websocket\r\n\
+Connection: Upgrade\r\n\
+Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+Sec-WebSocket-Version: 13\r\n\r\n";
+
+impl<'id> ServerSocketListener<'id> for RawClient {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = RawClientConnection;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        connection.write_buf.rw(owner).write_all(REQUEST).unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let expected = server.ro(owner).0;
+        let upgraded = connection.upgraded;
+        let read_buf = connection.read_buf.rw(owner);
+        if !upgraded {
+            assert!(read_buf.filled().starts_with(b"HTTP/1.1 101"));
+            assert!(read_buf.filled().ends_with(b"\r\n\r\n"));
+            read_buf.clear();
+            connection.upgraded = true;
+            let mask = [1, 2, 3, 4];
+            let mut frame = vec![0x82, 0x80 | 5];
+            frame.extend_from_slice(&mask);
+            frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            connection.write_buf.rw(owner).write_all(&frame).unwrap();
+            connection.register_flush_event(owner);
+        } else if read_buf.filled_len() >= expected.len() {
+            assert_eq!(read_buf.filled(), expected);
+            match expected {
+                b"\x82\x05hello" => ECHOED.store(true, Ordering::Relaxed),
+                _ => RAW_FRAMED.store(true, Ordering::Relaxed),
+            }
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct Client;
+#[derive(Default)]
+pub struct ClientConnection<'id> {
+    websocket: LCell<'id, WebSocket>,
+    sent: bool,
+}
+
+impl<'id> ServerSocketListener<'id> for Client {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ClientConnection<'id>;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        websocket_connect(
+            owner,
+            &connection.websocket,
+            &connection.write_buf,
+            "example.com",
+            "/",
+            &["chat"],
+        )
+        .unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        match websocket_client_read(
+            owner,
+            &connection.websocket,
+            &connection.read_buf,
+            &connection.write_buf,
+        ) {
+            Ok(message) => {
+                let read_buf = connection.read_buf.ro(owner);
+                let payload = &read_buf.filled()[read_buf.pos()..read_buf.pos() + message.len];
+                assert_eq!(payload, b"hello");
+                CLIENT_ECHOED.store(true, Ordering::Relaxed);
+                connection.register_close_event(owner);
+            }
+            Err(ReadError::NotFullRead) => {
+                if connection.websocket.ro(owner).state == WebSocketState::Accepted
+                    && !connection.sent
+                {
+                    connection.sent = true;
+                    connection.write_buf.rw(owner).write_all(b"hello").unwrap();
+                    connection.register_flush_event(owner);
+                }
+            }
+            Err(_) => connection.register_close_event(owner),
+        }
+    }
+
+    fn flush(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        if websocket_client_flush(owner, &connection.websocket, &connection.write_buf).is_err() {
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+fn burst() -> Vec<u8> {
+    (0..8u8)
+        .flat_map(|i| [0x82, 100].into_iter().chain([i; 100]))
+        .collect()
+}
+
+pub struct BurstServer;
+
+impl<'id> WebSocketHandler<'id> for BurstServer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 512;
+    const WRITE_BUFFER_LEN: usize = 512;
+    const WRITE_HIGH_WATER_MARK: usize = 1024;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, WebSocketListener<Self>>,
+    ) {
+    }
+
+    fn on_open(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+        for i in 0..8 {
+            connection.send(owner, &[i; 100]).unwrap();
+        }
+        assert_ne!(connection.overflow_len(), 0);
+    }
+
+    fn on_message(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+        _message: Message,
+    ) {
+    }
+
+    fn on_close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, WebSocketListener<Self>>,
+    ) {
+    }
+}
+
+pub struct BurstClient;
+
+impl<'id> ServerSocketListener<'id> for BurstClient {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 2048;
+    const WRITE_BUFFER_LEN: usize = 512;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        connection.write_buf.rw(owner).write_all(REQUEST).unwrap();
+        connection.register_flush_event(owner);
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let received = connection.read_buf.ro(owner).filled();
+        let Some(end) = received.windows(4).position(|window| window == b"\r\n\r\n") else {
+            return;
+        };
+        let frames = &received[end + 4..];
+        assert!(burst().starts_with(frames));
+        if frames.len() == burst().len() {
+            BURST_RECEIVED.store(true, Ordering::Relaxed);
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
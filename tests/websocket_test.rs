@@ -0,0 +1,391 @@
+#![cfg(feature = "websocket")]
+
+use std::io::Write;
+
+use fast_collections::Cursor;
+use qcell::{LCell, LCellOwner};
+use socket_server::websocket::{
+    websocket_flush, websocket_read, websocket_send, HandshakeResponse, ReadError, WebSocket,
+    WebSocketConfig, WebSocketPolicy, WebSocketState,
+};
+
+const REQUEST: &[u8] = b"GET /chat HTTP/1.1\r\n\
+Host: example.com\r\n\
+Upgrade: websocket\r\n\
+Connection: Upgrade\r\n\
+Origin: https://example.com\r\n\
+Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+Sec-WebSocket-Protocol: chat.v1, chat.v2\r\n\
+Sec-WebSocket-Version: 13\r\n\r\n";
+
+#[cfg(feature = "websocket-deflate")]
+const DEFLATE_REQUEST: &[u8] = b"GET / HTTP/1.1\r\n\
+Host: example.com\r\n\
+Upgrade: websocket\r\n\
+Connection: Upgrade\r\n\
+Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\
+Sec-WebSocket-Version: 13\r\n\r\n";
+
+#[test]
+fn test_handshake_accepts_with_protocol() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        read_buf.rw(&mut owner).write_all(REQUEST).unwrap();
+        let config = WebSocketConfig::DEFAULT;
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |request| {
+                assert_eq!(request.path, "/chat");
+                assert_eq!(request.origin, Some("https://example.com"));
+                HandshakeResponse::Accept {
+                    protocol: request.protocols().find(|protocol| *protocol == "chat.v2"),
+                    headers: &[("X-Server", "test")],
+                }
+            },
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::HandShaked);
+        let response = std::str::from_utf8(write_buf.ro(&owner).filled()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(response.contains("Sec-WebSocket-Protocol: chat.v2\r\n"));
+        assert!(response.ends_with("X-Server: test\r\n\r\n"));
+    })
+}
+
+#[test]
+fn test_handshake_rejects_unoffered_protocol() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        read_buf.rw(&mut owner).write_all(REQUEST).unwrap();
+        let config = WebSocketConfig::DEFAULT;
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| HandshakeResponse::Accept {
+                protocol: Some("chat.v3"),
+                headers: &[],
+            },
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        let response = std::str::from_utf8(write_buf.ro(&owner).filled()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    })
+}
+
+#[test]
+fn test_handshake_rejects_cross_origin() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        read_buf.rw(&mut owner).write_all(REQUEST).unwrap();
+        let config = WebSocketConfig::DEFAULT;
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |request| match request.origin {
+                Some("https://game.example.com") => HandshakeResponse::Accept {
+                    protocol: None,
+                    headers: &[],
+                },
+                _ => HandshakeResponse::Reject { status: 403 },
+            },
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        let response = std::str::from_utf8(write_buf.ro(&owner).filled()).unwrap();
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    })
+}
+
+#[cfg(feature = "websocket-deflate")]
+#[test]
+fn test_permessage_deflate_round_trip() {
+    use socket_server::websocket::DeflateConfig;
+
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let config = WebSocketConfig {
+            deflate: Some(DeflateConfig {
+                server_no_context_takeover: true,
+                ..DeflateConfig::DEFAULT
+            }),
+            ..WebSocketConfig::DEFAULT
+        };
+        read_buf.rw(&mut owner).write_all(DEFLATE_REQUEST).unwrap();
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| HandshakeResponse::Accept {
+                protocol: None,
+                headers: &[],
+            },
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        let response = std::str::from_utf8(write_buf.ro(&owner).filled()).unwrap();
+        assert!(response.contains(
+            "Sec-WebSocket-Extensions: permessage-deflate; server_no_context_takeover\r\n"
+        ));
+        assert!(websocket.ro(&owner).is_deflate_negotiated());
+        websocket_flush(&mut owner, &websocket, &write_buf).unwrap();
+        write_buf.rw(&mut owner).clear();
+
+        let payload = [7u8; 200];
+        write_buf.rw(&mut owner).write_all(&payload).unwrap();
+        websocket_flush(&mut owner, &websocket, &write_buf).unwrap();
+        let frame = write_buf.ro(&owner).filled().to_vec();
+        assert_eq!(frame[0], 0b1100_0010);
+        assert!(frame.len() < payload.len());
+
+        read_buf
+            .rw(&mut owner)
+            .write_all(&masked_frame(frame[0], &frame[2..]))
+            .unwrap();
+        let message = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| unreachable!(),
+        )
+        .ok()
+        .unwrap();
+        let read_buf = read_buf.ro(&owner);
+        assert_eq!(
+            &read_buf.filled()[read_buf.pos()..read_buf.pos() + message.len],
+            &payload
+        );
+    })
+}
+
+#[cfg(feature = "websocket-deflate")]
+#[test]
+fn test_invalid_compressed_payload_is_rejected() {
+    use socket_server::websocket::DeflateConfig;
+
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let config = WebSocketConfig {
+            deflate: Some(DeflateConfig::DEFAULT),
+            ..WebSocketConfig::DEFAULT
+        };
+        accept_with(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            DEFLATE_REQUEST,
+        );
+
+        read_buf
+            .rw(&mut owner)
+            .write_all(&masked_frame(0b1100_0010, &[0xff; 8]))
+            .unwrap();
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| unreachable!(),
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        assert_eq!(write_buf.ro(&owner).filled(), b"\x88\x02\x03\xef");
+    })
+}
+
+#[cfg(feature = "websocket-deflate")]
+#[test]
+fn test_oversized_inflated_payload_is_rejected() {
+    use socket_server::websocket::DeflateConfig;
+
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let mut config = WebSocketConfig {
+            deflate: Some(DeflateConfig::DEFAULT),
+            ..WebSocketConfig::DEFAULT
+        };
+        accept_with(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            DEFLATE_REQUEST,
+        );
+
+        write_buf.rw(&mut owner).write_all(&[7; 200]).unwrap();
+        websocket_flush(&mut owner, &websocket, &write_buf).unwrap();
+        let frame = write_buf.ro(&owner).filled().to_vec();
+        write_buf.rw(&mut owner).clear();
+        read_buf
+            .rw(&mut owner)
+            .write_all(&masked_frame(frame[0], &frame[2..]))
+            .unwrap();
+        config.policy = WebSocketPolicy {
+            max_message_size: 100,
+            ..WebSocketPolicy::DEFAULT
+        };
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| unreachable!(),
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        assert_eq!(write_buf.ro(&owner).filled(), b"\x88\x02\x03\xf1");
+    })
+}
+
+#[test]
+fn test_unmasked_frame_is_rejected() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let config = WebSocketConfig::DEFAULT;
+        accept(&mut owner, &websocket, &read_buf, &write_buf, &config);
+
+        read_buf.rw(&mut owner).write_all(b"\x82\x05hello").unwrap();
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| unreachable!(),
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        assert_eq!(write_buf.ro(&owner).filled(), b"\x88\x02\x03\xea");
+    })
+}
+
+#[test]
+fn test_oversized_frame_is_rejected() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let mut config = WebSocketConfig::DEFAULT;
+        config.policy = WebSocketPolicy {
+            max_frame_size: 4,
+            ..WebSocketPolicy::DEFAULT
+        };
+        accept(&mut owner, &websocket, &read_buf, &write_buf, &config);
+
+        read_buf
+            .rw(&mut owner)
+            .write_all(&masked_frame(0x82, b"hello"))
+            .unwrap();
+        let result = websocket_read(
+            &mut owner,
+            &websocket,
+            &read_buf,
+            &write_buf,
+            &config,
+            |_request| unreachable!(),
+        );
+        assert!(matches!(result, Err(ReadError::FlushRequest)));
+        assert!(websocket.ro(&owner).state == WebSocketState::Closing);
+        assert_eq!(write_buf.ro(&owner).filled(), b"\x88\x02\x03\xf1");
+    })
+}
+
+#[test]
+fn test_frames_are_written_in_place() {
+    LCellOwner::scope(|mut owner| {
+        let websocket = owner.cell(WebSocket::default());
+        let read_buf = owner.cell(Cursor::<u8, 512>::new());
+        let write_buf = owner.cell(Cursor::<u8, 512>::new());
+        let config = WebSocketConfig::DEFAULT;
+        accept(&mut owner, &websocket, &read_buf, &write_buf, &config);
+
+        write_buf.rw(&mut owner).write_all(b"abc").unwrap();
+        websocket_send(&mut owner, &websocket, &write_buf, &[7; 200]).unwrap();
+        websocket_flush(&mut owner, &websocket, &write_buf).unwrap();
+
+        let mut expected = b"\x82\x03abc\x82\x7e\x00\xc8".to_vec();
+        expected.extend_from_slice(&[7; 200]);
+        assert_eq!(write_buf.ro(&owner).filled(), expected.as_slice());
+        assert!(websocket_send(&mut owner, &websocket, &write_buf, &[0; 400]).is_err());
+        assert_eq!(write_buf.ro(&owner).filled(), expected.as_slice());
+    })
+}
+
+fn accept<'id>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    read_buf: &LCell<'id, Cursor<u8, 512>>,
+    write_buf: &LCell<'id, Cursor<u8, 512>>,
+    config: &WebSocketConfig,
+) {
+    accept_with(owner, websocket, read_buf, write_buf, config, REQUEST);
+}
+
+fn accept_with<'id>(
+    owner: &mut LCellOwner<'id>,
+    websocket: &LCell<'id, WebSocket>,
+    read_buf: &LCell<'id, Cursor<u8, 512>>,
+    write_buf: &LCell<'id, Cursor<u8, 512>>,
+    config: &WebSocketConfig,
+    request: &[u8],
+) {
+    read_buf.rw(owner).write_all(request).unwrap();
+    let result = websocket_read(owner, websocket, read_buf, write_buf, config, |_request| {
+        HandshakeResponse::Accept {
+            protocol: None,
+            headers: &[],
+        }
+    });
+    assert!(matches!(result, Err(ReadError::FlushRequest)));
+    websocket_flush(owner, websocket, write_buf).unwrap();
+    write_buf.rw(owner).clear();
+}
+
+fn masked_frame(header0: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![header0];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
@@ -0,0 +1,365 @@
+#![allow(incomplete_features)]
+#![feature(generic_const_exprs)]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use qcell::{LCell, LCellOwner};
+use socket_server::socket::{ServerSocketListener, Socket, Sockets};
+
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static QUEUE_FULL: AtomicBool = AtomicBool::new(false);
+static PARTIAL_WRITES: AtomicBool = AtomicBool::new(false);
+static FLUSH_REGISTRATIONS: AtomicUsize = AtomicUsize::new(0);
+static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+
+const STREAM_LEN: usize = 16 * 1024 * 1024;
+
+#[test]
+fn test_overflow_is_flushed_in_order() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(&mut owner, Receiver, Sender, Duration::from_millis(50))
+    });
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), 64);
+}
+
+#[test]
+fn test_high_water_mark_triggers_callback() {
+    LCellOwner::scope(|mut owner| {
+        socket_server::mock::run_mock(&mut owner, Receiver, Flooder, Duration::from_millis(50))
+    });
+    assert!(QUEUE_FULL.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_vectored_flush_over_tcp() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(
+                &mut owner,
+                BulkSender,
+                listener,
+                Duration::from_millis(50),
+            )
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut received = vec![0; 4096];
+    stream.read_exact(&mut received).unwrap();
+    assert!(received
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| byte == i as u8));
+}
+
+#[test]
+fn test_partial_write_keeps_order_of_later_writes() {
+    let addr = common::spawn_server(|listener| {
+        LCellOwner::scope(|mut owner| {
+            socket_server::mio::listen_on(&mut owner, Streamer, listener, Duration::from_millis(1))
+        })
+    });
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    thread::sleep(Duration::from_millis(300));
+    let mut received = vec![0; STREAM_LEN];
+    for chunk in received.chunks_mut(64 * 1024) {
+        stream.read_exact(chunk).unwrap();
+    }
+    assert!(received
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| byte == (i % 251) as u8));
+    assert!(PARTIAL_WRITES.load(Ordering::Relaxed));
+    assert!(FLUSHES.load(Ordering::Relaxed) <= FLUSH_REGISTRATIONS.load(Ordering::Relaxed));
+}
+
+pub struct Receiver;
+
+impl<'id> ServerSocketListener<'id> for Receiver {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 128;
+    const WRITE_BUFFER_LEN: usize = 128;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        let read_buf = connection.read_buf.rw(owner);
+        let received = RECEIVED.load(Ordering::Relaxed);
+        for (i, &byte) in read_buf.filled().iter().enumerate() {
+            assert_eq!(byte as usize, received + i);
+        }
+        let received = received + read_buf.filled_len();
+        read_buf.clear();
+        RECEIVED.store(received, Ordering::Relaxed);
+        if received == 64 {
+            connection.register_close_event(owner);
+        }
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct Sender;
+
+impl<'id> ServerSocketListener<'id> for Sender {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 128;
+    const WRITE_BUFFER_LEN: usize = 16;
+    const WRITE_HIGH_WATER_MARK: usize = 64;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        let data: Vec<u8> = (0..64).collect();
+        connection.write(owner, &data[..40]).unwrap();
+        connection.write(owner, &data[40..]).unwrap();
+        assert_eq!(connection.overflow_len(), 48);
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct Flooder;
+
+impl<'id> ServerSocketListener<'id> for Flooder {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 128;
+    const WRITE_BUFFER_LEN: usize = 16;
+    const WRITE_HIGH_WATER_MARK: usize = 32;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        assert!(connection.write(owner, &[0; 64]).is_err());
+        assert_eq!(connection.overflow_len(), 0);
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn write_queue_full(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+    ) {
+        QUEUE_FULL.store(true, Ordering::Relaxed);
+        connection.register_close_event(owner);
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct BulkSender;
+
+impl<'id> ServerSocketListener<'id> for BulkSender {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 256;
+    const WRITE_HIGH_WATER_MARK: usize = 8192;
+    type Connection = ();
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        _owner: &mut LCellOwner<'id>,
+        _sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+    }
+
+    fn accept(
+        owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+        let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+        connection.write(owner, &data).unwrap();
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}
+
+pub struct Streamer;
+
+impl<'id> ServerSocketListener<'id> for Streamer {
+    const MAX_CONNECTIONS: usize = 10;
+    const READ_BUFFFER_LEN: usize = 64;
+    const WRITE_BUFFER_LEN: usize = 64 * 1024;
+    type Connection = usize;
+
+    fn tick(
+        _server: &LCell<'id, Self>,
+        owner: &mut LCellOwner<'id>,
+        sockets: &mut Sockets<'id, '_, '_, Self>,
+    ) {
+        let metrics = sockets.metrics();
+        if metrics.partial_writes > 0 {
+            PARTIAL_WRITES.store(true, Ordering::Relaxed);
+        }
+        assert!(metrics.flush_events as usize <= FLUSH_REGISTRATIONS.load(Ordering::Relaxed));
+        for socket in sockets.iter_mut() {
+            let write_buf = socket.write_buf.rw(owner);
+            let len = (Self::WRITE_BUFFER_LEN - write_buf.filled_len()).min(STREAM_LEN - **socket);
+            if len == 0 {
+                continue;
+            }
+            let data: Vec<u8> = (**socket..**socket + len)
+                .map(|i| (i % 251) as u8)
+                .collect();
+            write_buf.write_all(&data).unwrap();
+            **socket += len;
+            socket.register_flush_event(owner);
+            FLUSH_REGISTRATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn accept(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+        _addr: std::net::SocketAddr,
+    ) {
+    }
+
+    fn read(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+
+    fn flush(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+        FLUSHES.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn close(
+        _owner: &mut LCellOwner<'id>,
+        _server: &LCell<'id, Self>,
+        _connection: &mut Socket<'id, '_, Self>,
+    ) {
+    }
+}